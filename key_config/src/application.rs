@@ -18,11 +18,53 @@ where
     serde_json::to_writer_pretty(std::io::BufWriter::new(file), value).map_err(|e| Error::json_file(e, path))
 }
 
+const MACROS_FILE_NAME: &str = "macros.json";
+/// The category of the macros in macros.json, which is not in order.json.
+const MACRO_CATEGORY: &str = "マクロ";
+/// The category of the actions in key_map.json.
+const CUSTOM_CATEGORY: &str = "カスタム";
+
+/// The user files merged into mmd_map.json.
+const OVERLAY_DIR: &str = "mmd_map.d";
+
+/// The categories of mmd_map.json with the overlays merged, and the conflicts of the overlays.
+fn read_mmd_map(
+    path: impl AsRef<std::path::Path>,
+) -> Result<(serde_json::Map<String, serde_json::Value>, Vec<OverlayError>), Error> {
+    let mmd_map: serde_json::Value = from_file(path)?;
+    let mut mmd_map = match mmd_map {
+        serde_json::Value::Object(m) => m,
+        _ => return Err(Error::InvalidData),
+    };
+    let (overlays, mut errors) = Overlay::load_dir(OVERLAY_DIR);
+    errors.extend(merge_overlays(&mut mmd_map, &overlays));
+    Ok((mmd_map, errors))
+}
+
+/// The IDs and the names of the macros. Empty if there is no macros.json.
+fn read_macros() -> Result<Vec<(String, String)>, Error> {
+    let macros: serde_json::Value = match from_file(MACROS_FILE_NAME) {
+        Ok(v) => v,
+        Err(Error::FileNotFound(_)) => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    macros
+        .as_array()
+        .ok_or(Error::InvalidData)?
+        .iter()
+        .map(|m| {
+            let id = m.get("id").and_then(|v| v.as_str()).ok_or(Error::InvalidData)?;
+            let name = m.get("name").and_then(|v| v.as_str()).unwrap_or(id);
+            Ok((id.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 struct Item {
     id: String,
     name: String,
-    keys: Keys,
+    keys: Bindings,
 }
 
 #[derive(Debug)]
@@ -32,7 +74,13 @@ struct Category {
 }
 
 #[derive(Debug)]
-struct KeyTable(Vec<Category>);
+struct KeyTable {
+    categories: Vec<Category>,
+    /// The context or the layer of the bindings in `categories`.
+    scope: EditScope,
+    /// The bindings of all contexts and layers as of the last `set_scope`.
+    key_map: KeyMap,
+}
 
 impl KeyTable {
     fn from_file(
@@ -40,18 +88,20 @@ impl KeyTable {
         order_path: impl AsRef<std::path::Path>,
         key_map_path: impl AsRef<std::path::Path>,
     ) -> Result<Self, Error> {
-        let mmd_map: serde_json::Value = from_file(mmd_map_path)?;
+        let (mmd_map, overlay_errors) = read_mmd_map(mmd_map_path)?;
+        for e in overlay_errors.iter() {
+            log::warn!("mmd_map.d: {}", e);
+        }
         let order: serde_json::Value = from_file(order_path)?;
-        let key_map: serde_json::Value = match from_file(&key_map_path) {
+        let key_map = match KeyMap::load(&key_map_path).map_err(|e| Error::file(e, &key_map_path)) {
             Ok(v) => v,
             Err(Error::FileNotFound(_)) => {
                 let key_map = KeyMap::default();
                 to_file(key_map_path, &key_map)?;
-                serde_json::to_value(&key_map).unwrap()
+                key_map
             }
             Err(e) => return Err(e),
         };
-        let mmd_map = mmd_map.as_object().ok_or(Error::InvalidData)?;
         let order = order.as_object().ok_or(Error::InvalidData)?;
         let category_order = order
             .get("categories")
@@ -62,51 +112,105 @@ impl KeyTable {
             .and_then(|a| a.as_object())
             .ok_or(Error::InvalidData)?;
         let mut table = vec![];
+        let mut categories = vec![];
         for category in category_order.iter() {
-            let category = category.as_str().ok_or(Error::InvalidData)?.to_string();
-            let item = mmd_map
-                .get(&category)
-                .and_then(|a| a.as_object())
-                .ok_or(Error::InvalidData)?;
-            let item_order = item_order
+            categories.push(category.as_str().ok_or(Error::InvalidData)?.to_string());
+        }
+        // The categories added by the overlays follow the ones in order.json.
+        categories.extend(
+            mmd_map
+                .keys()
+                .filter(|c| !categories.contains(c))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        for category in categories {
+            // The category hidden by an overlay.
+            let item = match mmd_map.get(&category) {
+                Some(item) => item.as_object().ok_or(Error::InvalidData)?,
+                None => continue,
+            };
+            let mut ids = vec![];
+            for id in item_order
                 .get(&category)
                 .and_then(|a| a.as_array())
-                .ok_or(Error::InvalidData)?;
+                .into_iter()
+                .flatten()
+            {
+                ids.push(id.as_str().ok_or(Error::InvalidData)?);
+            }
+            ids.extend(
+                item.keys()
+                    .map(|id| id.as_str())
+                    .filter(|id| !ids.contains(id))
+                    .collect::<Vec<_>>(),
+            );
             let mut v = vec![];
-            for id in item_order.iter() {
-                let id = id.as_str().ok_or(Error::InvalidData)?;
-                let name = item
-                    .get(id)
-                    .and_then(|a| a.as_array())
-                    .and_then(|a| a[0].as_str())
-                    .ok_or(Error::InvalidData)?;
-                let keys = key_map
-                    .get(id)
-                    .and_then(|v| v.as_array())
-                    .and_then(|a| {
-                        a.iter()
-                            .map(|v| v.as_u64().map(|v| v as u32))
-                            .collect::<Option<Vec<_>>>()
-                    })
-                    .map(|a| Keys::from_slice(&a))
-                    .unwrap_or_default();
+            for id in ids {
+                // The item hidden by an overlay.
+                let name = match item.get(id) {
+                    Some(a) => a
+                        .as_array()
+                        .and_then(|a| a.first())
+                        .and_then(|a| a.as_str())
+                        .ok_or(Error::InvalidData)?,
+                    None => continue,
+                };
+                let keys = key_map.get(id).cloned().unwrap_or_default();
                 v.push(Item {
                     id: id.to_string(),
-                    name: name.to_string(),
+                    name: catalog().action(id, name).to_string(),
                     keys,
                 });
             }
+            if v.is_empty() {
+                continue;
+            }
             table.push(Category {
                 name: category,
                 items: v,
             });
         }
-        Ok(Self(table))
+        let macros = read_macros()?;
+        if !macros.is_empty() {
+            let items = macros
+                .into_iter()
+                .map(|(id, name)| Item {
+                    keys: key_map.get(&id).cloned().unwrap_or_default(),
+                    id,
+                    name,
+                })
+                .collect();
+            table.push(Category {
+                name: MACRO_CATEGORY.to_string(),
+                items,
+            });
+        }
+        if key_map.actions().next().is_some() {
+            let items = key_map
+                .actions()
+                .map(|(id, action)| Item {
+                    id: id.clone(),
+                    name: action.name.clone(),
+                    keys: key_map.get(id).cloned().unwrap_or_default(),
+                })
+                .collect();
+            table.push(Category {
+                name: CUSTOM_CATEGORY.to_string(),
+                items,
+            });
+        }
+        Ok(Self {
+            categories: table,
+            scope: EditScope::default(),
+            key_map,
+        })
     }
 
-    fn to_file(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+    /// The bindings in `categories`.
+    fn scope_key_map(&self) -> KeyMap {
         let mut v = KeyMap::new();
-        for elem in self.0.iter().flat_map(|cat| &cat.items).filter_map(|item| {
+        for elem in self.categories.iter().flat_map(|cat| &cat.items).filter_map(|item| {
             if item.keys.is_empty() {
                 None
             } else {
@@ -115,27 +219,165 @@ impl KeyTable {
         }) {
             v.insert(elem.0, elem.1.clone());
         }
-        to_file(path, &v)
+        v
+    }
+
+    fn to_key_map(&self) -> KeyMap {
+        match &self.scope {
+            EditScope::Context(None) => {
+                let mut v = self.scope_key_map();
+                for (c, m) in self.key_map.contexts() {
+                    *v.context_mut(*c) = m.clone();
+                }
+                for (id, repeat) in self.key_map.repeats() {
+                    v.set_repeat(id, *repeat);
+                }
+                for (name, layer) in self.key_map.layers() {
+                    v.insert_layer(name, layer.clone());
+                }
+                for (id, action) in self.key_map.actions() {
+                    v.insert_action(id, action.clone());
+                }
+                v
+            }
+            EditScope::Context(Some(c)) => {
+                let mut v = self.key_map.clone();
+                *v.context_mut(*c) = self.scope_key_map();
+                v
+            }
+            EditScope::Layer(name) => {
+                let mut v = self.key_map.clone();
+                if let Some(layer) = v.layer_mut(name) {
+                    layer.key_map = self.scope_key_map();
+                }
+                v
+            }
+        }
+    }
+
+    fn to_file(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        to_file(path, &self.to_key_map())
+    }
+
+    #[inline]
+    fn scope(&self) -> &EditScope {
+        &self.scope
+    }
+
+    /// Replaces the bindings in `categories` with the bindings of `scope`.
+    /// A layer that does not exist is replaced with the bindings without a context.
+    fn set_scope(&mut self, scope: EditScope) {
+        self.key_map = self.to_key_map();
+        self.scope = match scope {
+            EditScope::Layer(name) if self.key_map.layer(&name).is_none() => EditScope::default(),
+            scope => scope,
+        };
+        let scope = match &self.scope {
+            EditScope::Context(None) => Some(&self.key_map),
+            EditScope::Context(Some(c)) => self.key_map.context(*c),
+            EditScope::Layer(name) => self.key_map.layer(name).map(|layer| &layer.key_map),
+        };
+        for item in self.categories.iter_mut().flat_map(|cat| &mut cat.items) {
+            item.keys = scope.and_then(|m| m.get(&item.id)).cloned().unwrap_or_default();
+        }
+    }
+
+    /// Checks the bindings in `categories`.
+    fn validate(&self) -> ValidationReport {
+        self.scope_key_map().validate(
+            self.categories
+                .iter()
+                .flat_map(|cat| &cat.items)
+                .map(|item| item.id.as_str()),
+        )
+    }
+
+    #[inline]
+    fn name_of<'a>(&'a self, id: &'a str) -> &'a str {
+        self.categories
+            .iter()
+            .flat_map(|cat| &cat.items)
+            .find(|item| item.id == id)
+            .map_or(id, |item| item.name.as_str())
     }
 
     #[inline]
     fn category_len(&self) -> usize {
-        self.0.len()
+        self.categories.len()
     }
 
     #[inline]
     fn iter(&self) -> std::slice::Iter<Category> {
-        self.0.iter()
+        self.categories.iter()
+    }
+
+    #[inline]
+    fn get(&self, category: usize, item: usize) -> &Bindings {
+        &self.categories[category].items[item].keys
+    }
+
+    #[inline]
+    fn set_keys(&mut self, category: usize, item: usize, keys: Bindings) {
+        self.categories[category].items[item].keys = keys;
+    }
+
+    /// The key and the mode of the layers, sorted by name.
+    #[inline]
+    fn layers(&self) -> Vec<(String, &Layer)> {
+        self.key_map
+            .layers()
+            .map(|(name, layer)| (name.clone(), layer))
+            .collect()
+    }
+
+    #[inline]
+    fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.key_map.layer_mut(name)
+    }
+
+    /// Returns `false` if a layer named `name` already exists.
+    fn insert_layer(&mut self, name: &str, layer: Layer) -> bool {
+        self.key_map = self.to_key_map();
+        if self.key_map.layer(name).is_some() {
+            return false;
+        }
+        self.key_map.insert_layer(name, layer);
+        true
     }
 
+    /// Returns `false` if a layer named `new_name` already exists.
+    fn rename_layer(&mut self, name: &str, new_name: &str) -> bool {
+        self.key_map = self.to_key_map();
+        if self.key_map.layer(new_name).is_some() {
+            return false;
+        }
+        if let Some(layer) = self.key_map.remove_layer(name) {
+            self.key_map.insert_layer(new_name, layer);
+            if self.scope == EditScope::Layer(name.to_string()) {
+                self.scope = EditScope::Layer(new_name.to_string());
+            }
+        }
+        true
+    }
+
+    fn remove_layer(&mut self, name: &str) {
+        self.key_map = self.to_key_map();
+        self.key_map.remove_layer(name);
+        if self.scope == EditScope::Layer(name.to_string()) {
+            self.set_scope(EditScope::default());
+        }
+    }
+
+    /// The repeat applies to all contexts and layers.
     #[inline]
-    fn get(&self, category: usize, item: usize) -> &Keys {
-        &(self.0)[category].items[item].keys
+    fn repeat(&self, category: usize, item: usize) -> Repeat {
+        self.key_map.repeat(&self.categories[category].items[item].id)
     }
 
     #[inline]
-    fn set_keys(&mut self, category: usize, item: usize, keys: Option<Keys>) {
-        (self.0)[category].items[item].keys = keys.unwrap_or_default();
+    fn set_repeat(&mut self, category: usize, item: usize, repeat: Repeat) {
+        self.key_map
+            .set_repeat(&self.categories[category].items[item].id, repeat);
     }
 }
 
@@ -143,7 +385,7 @@ impl std::ops::Index<usize> for KeyTable {
     type Output = Category;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
+        &self.categories[index]
     }
 }
 
@@ -153,6 +395,10 @@ const SETTINGS_FILE_NAME: &str = "key_config_settrings.json";
 struct Settings {
     window_position: wita::ScreenPosition,
     window_size: wita::LogicalSize<u32>,
+    #[serde(default)]
+    layout: Option<key_map::Layout>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
 }
 
 impl Settings {
@@ -175,10 +421,95 @@ impl Default for Settings {
         Self {
             window_position: (0, 0).into(),
             window_size: (710, 526).into(),
+            layout: None,
+            language: None,
         }
     }
 }
 
+fn current_keyboard_layout() -> key_map::Layout {
+    let hkl = unsafe { GetKeyboardLayout(0) };
+    key_map::Layout::from_lang_id((hkl.0 & 0xffff) as u16).unwrap_or_default()
+}
+
+/// The language passed by MMAccel with `--language`.
+fn language_arg() -> Option<Language> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--language").skip(1);
+    args.next().and_then(|arg| arg.parse().ok())
+}
+
+/// The mmd_map file passed by MMAccel with `--mmd-map`, which MMAccel has chosen for the version of MMD.
+fn mmd_map_arg() -> String {
+    let mut args = std::env::args().skip_while(|arg| arg != "--mmd-map").skip(1);
+    args.next().unwrap_or_else(|| "mmd_map.json".to_string())
+}
+
+const LOCALE_DIR: &str = "locale";
+
+/// Warns about the IDs that are dropped when the profile is saved.
+fn check_unknown_ids(mmd_map_path: &str, key_map_path: &std::path::Path) -> Result<(), Error> {
+    let key_map = match KeyMap::from_file(key_map_path) {
+        Ok(key_map) => key_map,
+        Err(_) => return Ok(()),
+    };
+    let (mmd_map, _) = read_mmd_map(mmd_map_path)?;
+    let ids = mmd_map
+        .values()
+        .filter_map(|items| items.as_object())
+        .flat_map(|items| items.keys().map(|id| id.as_str()))
+        .collect::<Vec<_>>();
+    let macros = read_macros().unwrap_or_default();
+    let ids = ids
+        .into_iter()
+        .chain(macros.iter().map(|(id, _)| id.as_str()))
+        .chain(key_map.actions().map(|(id, _)| id.as_str()))
+        .collect::<Vec<_>>();
+    let mut unknown = std::iter::once(&key_map)
+        .chain(key_map.contexts().map(|(_, m)| m))
+        .chain(key_map.layers().map(|(_, layer)| &layer.key_map))
+        .flat_map(|m| m.validate(ids.iter().copied()))
+        .filter_map(|d| match d {
+            Diagnostic::UnknownId { id } => Some(id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    unknown.sort();
+    unknown.dedup();
+    let unknown = unknown.join(", ");
+    if !unknown.is_empty() {
+        log::warn!("unknown ids: {}", unknown);
+        let file_name = key_map_path.file_name().unwrap_or_default().to_string_lossy();
+        message_box(
+            None,
+            tr!("key_config.unknown_ids", file_name, unknown),
+            tr!("key_config.title"),
+            MB_OK | MB_ICONWARNING,
+        );
+    }
+    Ok(())
+}
+
+/// Reads "delay, interval" in milliseconds. The interval cannot be 0.
+fn parse_repeat(text: &str) -> Option<Repeat> {
+    let v = text
+        .split(|c: char| c == ',' || c == '、' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match v[..] {
+        [delay, interval] if interval > 0 => Some(Repeat::Custom { delay, interval }),
+        _ => None,
+    }
+}
+
+fn profile_error(e: std::io::Error, name: &str) -> String {
+    match e.kind() {
+        std::io::ErrorKind::AlreadyExists => tr!("key_config.already_exists", name),
+        std::io::ErrorKind::InvalidInput => tr!("profile.invalid_name", name),
+        _ => tr!("error.io", e),
+    }
+}
+
 const MARGIN: i32 = 10;
 const SIDE_MENU_WIDTH: i32 = 150;
 const SHORTCUT_MENU_NAME_COLUMN_WIDTH: i32 = 187;
@@ -218,7 +549,13 @@ pub struct Application {
     shortcut_list: ShortcutList,
     editor: Box<Editor>,
     key_table: KeyTable,
+    mmd_map: String,
     popup_menu: PopupMenu,
+    profiles: Profiles,
+    profile: String,
+    profile_menu: ProfileMenu,
+    context_menu: ContextMenu,
+    key_layout: key_map::Layout,
 }
 
 impl Application {
@@ -228,9 +565,20 @@ impl Application {
                 SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT(-3));
             }
         }
-        let settings = Settings::from_file()?;
+        let settings = Settings::from_file();
+        let language = settings
+            .as_ref()
+            .ok()
+            .and_then(|settings| settings.language)
+            .or_else(language_arg)
+            .unwrap_or_default();
+        set_catalog(Catalog::load(LOCALE_DIR, language).unwrap_or_else(|e| {
+            log::error!("{}.json: {}", language, e);
+            Catalog::builtin(language)
+        }));
+        let settings = settings?;
         let main_window = wita::WindowBuilder::new()
-            .title("MMAccel キー設定")
+            .title(tr!("key_config.title"))
             .position(settings.window_position)
             .inner_size(settings.window_size)
             .icon(wita::Icon::Resource(101))
@@ -257,31 +605,57 @@ impl Application {
                 );
             }
         }
-        let key_table = KeyTable::from_file("mmd_map.json", "order.json", "key_map.json")?;
+        let profiles = Profiles::new(".");
+        let profile = profiles.current();
+        log::debug!("profile: {}", profile);
+        let mmd_map = mmd_map_arg();
+        log::debug!("mmd_map: {}", mmd_map);
+        let key_table = KeyTable::from_file(&mmd_map, "order.json", profiles.path(&profile))?;
+        check_unknown_ids(&mmd_map, &profiles.path(&profile))?;
+        let key_layout = settings.layout.unwrap_or_else(current_keyboard_layout);
+        log::debug!("keyboard layout: {}", key_layout);
+        let (mut profile_menu, mut context_menu) = unsafe {
+            let menu_bar = CreateMenu();
+            let menus = (ProfileMenu::new(menu_bar), ContextMenu::new(menu_bar, key_layout));
+            SetMenu(HWND(main_window.raw_handle() as _), menu_bar);
+            menus
+        };
+        profile_menu.update(&main_window, profiles.names(), &profile);
+        context_menu.update(&main_window, key_table.scope(), key_table.layers());
         let layout = calc_layout(settings.window_size);
         let mut side_menu = SideMenu::new(&main_window, layout.side_menu.position, layout.side_menu.size)?;
-        key_table.iter().for_each(|cat| side_menu.push(&cat.name));
+        key_table
+            .iter()
+            .for_each(|cat| side_menu.push(catalog().category(&cat.name)));
         side_menu.set_index(0);
         let mut shortcut_list = ShortcutList::new(
             &main_window,
             layout.shortcut_list.position,
             layout.shortcut_list.size,
             [SHORTCUT_MENU_NAME_COLUMN_WIDTH, SHORTCUT_MENU_KEYS_COLUMN_WIDTH],
+            key_layout,
         )?;
         key_table[0]
             .items
             .iter()
             .for_each(|item| shortcut_list.push(&item.name, &item.keys));
-        let editor = Editor::new(shortcut_list.handle())?;
+        let editor = Editor::new(shortcut_list.handle(), key_layout)?;
         let mut app = Box::new(Self {
             settings,
             main_window,
             side_menu,
             shortcut_list,
             key_table,
+            mmd_map,
             editor,
-            popup_menu: PopupMenu::new(),
+            popup_menu: PopupMenu::new(key_layout),
+            profiles,
+            profile,
+            profile_menu,
+            context_menu,
+            key_layout,
         });
+        app.update_title();
         unsafe {
             let hwnd = HWND(app.main_window.raw_handle() as _);
             let app_ptr = app.as_mut() as *mut Self;
@@ -290,38 +664,375 @@ impl Application {
         Ok(app)
     }
 
-    fn update_keys_to_file(&mut self, category: usize, item: usize, keys: Option<Keys>) {
+    fn update_title(&self) {
+        let title = match self.key_table.scope() {
+            EditScope::Context(None) => format!("{} - {}", tr!("key_config.title"), Profiles::label(&self.profile)),
+            scope => format!(
+                "{} - {} ({})",
+                tr!("key_config.title"),
+                Profiles::label(&self.profile),
+                scope_label(scope)
+            ),
+        };
+        let title = to_wchar(title);
+        unsafe {
+            SetWindowTextW(HWND(self.main_window.raw_handle() as _), PWSTR(title.as_ptr() as _));
+        }
+    }
+
+    #[inline]
+    fn save(&self) {
+        if let Err(e) = self.key_table.to_file(self.profiles.path(&self.profile)) {
+            log::error!("{}", e);
+        }
+    }
+
+    fn select_profile(&mut self, name: &str) -> Result<(), Error> {
+        if self.editor.is_visible() {
+            if let Some(ret) = self.editor.end() {
+                self.apply_edit(ret);
+            }
+        }
+        let path = self.profiles.path(name);
+        let scope = self.key_table.scope().clone();
+        self.key_table = KeyTable::from_file(&self.mmd_map, "order.json", &path)?;
+        self.key_table.set_scope(scope);
+        check_unknown_ids(&self.mmd_map, &path)?;
+        self.profile = name.to_string();
+        if let Err(e) = self.profiles.set_current(name) {
+            log::error!("profile.json: {}", e);
+        }
+        log::debug!("profile: {}", self.profile);
+        self.reload_side_menu();
+        self.reload_shortcut_list();
+        self.update_profile_menu();
+        self.update_context_menu();
+        self.update_title();
+        Ok(())
+    }
+
+    fn select_scope(&mut self, scope: EditScope) {
+        if self.editor.is_visible() {
+            if let Some(ret) = self.editor.end() {
+                self.apply_edit(ret);
+            }
+        }
+        self.key_table.set_scope(scope);
+        log::debug!("scope: {:?}", self.key_table.scope());
+        self.reload_shortcut_list();
+        self.update_context_menu();
+        self.update_title();
+    }
+
+    #[inline]
+    fn update_context_menu(&mut self) {
+        self.context_menu
+            .update(&self.main_window, self.key_table.scope(), self.key_table.layers());
+    }
+
+    fn layer_command(&mut self, id: u32) {
+        if self.editor.is_visible() {
+            if let Some(ret) = self.editor.end() {
+                self.apply_edit(ret);
+            }
+        }
+        let current = match self.key_table.scope() {
+            EditScope::Layer(name) => Some(name.clone()),
+            _ => None,
+        };
+        let hwnd = HWND(self.main_window.raw_handle() as _);
+        match (id, current) {
+            (IDM_LAYER_NEW, _) => {
+                let name = match input_name(&self.main_window, tr!("layer.new"), "") {
+                    Some(name) if !name.is_empty() => name,
+                    _ => return,
+                };
+                if !self
+                    .key_table
+                    .insert_layer(&name, Layer::new(Keys::new(), LayerMode::default()))
+                {
+                    message_box(
+                        Some(hwnd),
+                        tr!("key_config.already_exists", name),
+                        tr!("key_config.title"),
+                        MB_OK | MB_ICONERROR,
+                    );
+                    return;
+                }
+                self.select_scope(EditScope::Layer(name));
+                self.input_layer_key();
+                return;
+            }
+            (IDM_LAYER_KEY, Some(_)) => {
+                self.input_layer_key();
+                return;
+            }
+            (IDM_LAYER_RENAME, Some(name)) => {
+                let new_name = match input_name(&self.main_window, tr!("layer.rename"), &name) {
+                    Some(new_name) if !new_name.is_empty() && new_name != name => new_name,
+                    _ => return,
+                };
+                if !self.key_table.rename_layer(&name, &new_name) {
+                    message_box(
+                        Some(hwnd),
+                        tr!("key_config.already_exists", new_name),
+                        tr!("key_config.title"),
+                        MB_OK | MB_ICONERROR,
+                    );
+                    return;
+                }
+            }
+            (IDM_LAYER_DELETE, Some(name)) => {
+                let text = tr!("layer.delete_confirm", name);
+                if message_box(Some(hwnd), text, tr!("key_config.title"), MB_YESNO | MB_ICONWARNING) != IDYES {
+                    return;
+                }
+                self.key_table.remove_layer(&name);
+                self.reload_shortcut_list();
+            }
+            (id @ IDM_LAYER_MODE..=IDM_LAYER_MODE_LAST, Some(name)) => {
+                if let (Some(mode), Some(layer)) = (ContextMenu::layer_mode(id), self.key_table.layer_mut(&name)) {
+                    layer.mode = mode;
+                }
+            }
+            _ => return,
+        }
+        self.save();
+        self.update_context_menu();
+        self.update_title();
+    }
+
+    /// Sets the key of the layer being edited.
+    fn input_layer_key(&mut self) {
+        let name = match self.key_table.scope() {
+            EditScope::Layer(name) => name.clone(),
+            _ => return,
+        };
+        let key = self
+            .key_table
+            .layers()
+            .into_iter()
+            .find(|(n, _)| *n == name)
+            .map_or(String::new(), |(_, layer)| layer.key.to_string_in(self.key_layout));
+        let text = match input_name(&self.main_window, tr!("layer.key_prompt"), &key) {
+            Some(text) => text,
+            None => return,
+        };
+        let key = if text.is_empty() {
+            Ok(Keys::new())
+        } else {
+            Keys::from_str_in(&text, self.key_layout)
+        };
+        match key {
+            Ok(key) => {
+                if let Some(layer) = self.key_table.layer_mut(&name) {
+                    layer.key = key;
+                }
+                self.save();
+                self.update_context_menu();
+            }
+            Err(_) => {
+                let hwnd = HWND(self.main_window.raw_handle() as _);
+                message_box(
+                    Some(hwnd),
+                    tr!("layer.invalid_key", text),
+                    tr!("key_config.title"),
+                    MB_OK | MB_ICONERROR,
+                );
+            }
+        }
+    }
+
+    /// The categories change with the actions of the profile.
+    fn reload_side_menu(&mut self) {
+        let index = self.side_menu.current_index();
+        self.side_menu.clear();
+        self.key_table
+            .iter()
+            .for_each(|cat| self.side_menu.push(catalog().category(&cat.name)));
+        let index = if index < self.key_table.category_len() {
+            index
+        } else {
+            0
+        };
+        self.side_menu.set_index(index as _);
+    }
+
+    fn reload_shortcut_list(&mut self) {
+        self.shortcut_list.clear();
+        for item in self.key_table[self.side_menu.current_index()].items.iter() {
+            self.shortcut_list.push(&item.name, &item.keys);
+        }
+        self.update_shortcut_list();
+    }
+
+    #[inline]
+    fn update_profile_menu(&mut self) {
+        self.profile_menu
+            .update(&self.main_window, self.profiles.names(), &self.profile);
+    }
+
+    fn profile_command(&mut self, id: u32) {
+        let ret = match id {
+            IDM_PROFILE_NEW => input_name(&self.main_window, tr!("profile.new"), "").map(|name| {
+                self.profiles
+                    .create(&name)
+                    .map_err(|e| profile_error(e, &name))
+                    .map(|_| Some(name))
+            }),
+            IDM_PROFILE_DUPLICATE => {
+                let text = tr!("profile.copy_name", Profiles::label(&self.profile));
+                input_name(&self.main_window, tr!("profile.duplicate"), &text).map(|name| {
+                    self.profiles
+                        .duplicate(&self.profile, &name)
+                        .map_err(|e| profile_error(e, &name))
+                        .map(|_| Some(name))
+                })
+            }
+            IDM_PROFILE_RENAME => input_name(&self.main_window, tr!("profile.rename"), &self.profile).map(|name| {
+                self.profiles
+                    .rename(&self.profile, &name)
+                    .map_err(|e| profile_error(e, &name))
+                    .map(|_| Some(name))
+            }),
+            IDM_PROFILE_DELETE => {
+                let text = tr!("key_config.delete_confirm", Profiles::label(&self.profile));
+                let hwnd = HWND(self.main_window.raw_handle() as _);
+                (message_box(Some(hwnd), text, tr!("key_config.title"), MB_YESNO | MB_ICONWARNING) == IDYES).then(
+                    || {
+                        self.profiles
+                            .delete(&self.profile)
+                            .map_err(|e| profile_error(e, &self.profile))
+                            .map(|_| Some(DEFAULT_PROFILE.to_string()))
+                    },
+                )
+            }
+            _ => Some(Ok(self.profile_menu.name(id).map(|name| name.to_string()))),
+        };
+        let ret = match ret {
+            Some(Ok(Some(name))) => self.select_profile(&name).map_err(|e| e.to_string()),
+            Some(Err(e)) => Err(e),
+            _ => Ok(()),
+        };
+        if let Err(e) = ret {
+            log::error!("{}", e);
+            let hwnd = HWND(self.main_window.raw_handle() as _);
+            message_box(Some(hwnd), e, tr!("key_config.title"), MB_OK | MB_ICONERROR);
+            self.update_profile_menu();
+        }
+    }
+
+    fn apply_edit(&mut self, ret: EditResult) {
+        let mut keys = self.key_table.get(ret.category, ret.item).clone();
+        // Changing the keys keeps the trigger.
+        let trigger = keys.get(ret.index).map_or(Trigger::Press, |seq| seq.trigger());
+        keys.set(ret.index, ret.keys.with_trigger(trigger));
+        self.update_keys_to_file(ret.category, ret.item, keys);
+    }
+
+    fn update_keys_to_file(&mut self, category: usize, item: usize, keys: Bindings) {
         self.update_keys(category, item, keys);
-        self.key_table.to_file("key_map.json").ok();
+        self.save();
         self.update_shortcut_list();
     }
 
-    fn update_keys(&mut self, category: usize, item: usize, keys: Option<Keys>) {
+    fn update_keys(&mut self, category: usize, item: usize, keys: Bindings) {
         if category == self.side_menu.current_index() {
-            self.shortcut_list
-                .set_keys(item, keys.as_ref().unwrap_or(&Keys::default()));
+            self.shortcut_list.set_keys(item, &keys);
         }
         self.key_table.set_keys(category, item, keys);
     }
 
-    fn update_shortcut_list(&mut self) {
-        let category = self.side_menu.current_index();
-        for (index, item) in self.key_table[category].items.iter().enumerate() {
-            if item.keys.is_empty() {
-                self.shortcut_list.set_dup(index, None);
-                continue;
+    fn update_repeat(&mut self, category: usize, item: usize, repeat: Repeat) {
+        self.key_table.set_repeat(category, item, repeat);
+        self.save();
+    }
+
+    /// Asks for the delay and the interval such as "300, 50".
+    fn input_repeat(&mut self, category: usize, item: usize) {
+        let (delay, interval) = match self.key_table.repeat(category, item) {
+            Repeat::Custom { delay, interval } => (delay, interval),
+            _ => (Repeat::DEFAULT_DELAY, Repeat::DEFAULT_INTERVAL),
+        };
+        let text = match input_name(
+            &self.main_window,
+            tr!("repeat.prompt"),
+            &format!("{}, {}", delay, interval),
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+        match parse_repeat(&text) {
+            Some(repeat) => self.update_repeat(category, item, repeat),
+            None => {
+                let hwnd = HWND(self.main_window.raw_handle() as _);
+                message_box(
+                    Some(hwnd),
+                    tr!("repeat.invalid"),
+                    tr!("key_config.title"),
+                    MB_OK | MB_ICONERROR,
+                );
             }
-            let dup = self
+        }
+    }
+
+    fn begin_edit(&mut self, category: usize, item: usize, index: usize, sided: bool) {
+        if let Some(rc) = self.shortcut_list.keys_rect(item) {
+            let keys = self
                 .key_table
+                .get(category, item)
+                .get(index)
+                .cloned()
+                .unwrap_or_default();
+            let sided = sided || keys.is_sided();
+            self.editor.begin(&rc, category, item, index, &keys, sided);
+        }
+    }
+
+    fn diagnostic_text(&self, id: &str, d: &Diagnostic) -> String {
+        let layout = self.shortcut_list.layout();
+        match d {
+            Diagnostic::Duplicate { ids, .. } => ids
                 .iter()
-                .flat_map(|cat| &cat.items)
-                .filter(|i| i.id != item.id && !i.keys.is_empty() && i.keys == item.keys)
-                .map(|i| i.name.as_str())
+                .filter(|i| *i != id)
+                .map(|i| self.key_table.name_of(i))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Diagnostic::Shadowed {
+                id: i,
+                seq,
+                prefix,
+                prefix_id,
+            } if i == id => tr!(
+                "diagnostic.shadowed",
+                sequence_text(seq, layout),
+                sequence_text(prefix, layout),
+                self.key_table.name_of(prefix_id)
+            ),
+            Diagnostic::Shadowed { id: i, seq, prefix, .. } => tr!(
+                "diagnostic.shadowing",
+                sequence_text(prefix, layout),
+                sequence_text(seq, layout),
+                self.key_table.name_of(i)
+            ),
+            Diagnostic::ModifierOnly { seq, .. } => tr!("diagnostic.modifier_only", sequence_text(seq, layout)),
+            Diagnostic::Reserved { seq, .. } => tr!("diagnostic.reserved", sequence_text(seq, layout)),
+            Diagnostic::UnknownId { id } => tr!("diagnostic.unknown_id", id),
+        }
+    }
+
+    fn update_shortcut_list(&mut self) {
+        let category = self.side_menu.current_index();
+        let report = self.key_table.validate();
+        for (index, item) in self.key_table[category].items.iter().enumerate() {
+            let text = report
+                .of(&item.id)
+                .map(|d| self.diagnostic_text(&item.id, d))
                 .collect::<Vec<_>>();
-            if dup.is_empty() {
+            if text.is_empty() {
                 self.shortcut_list.set_dup(index, None);
             } else {
-                self.shortcut_list.set_dup(index, Some(&dup.join(", ")));
+                self.shortcut_list.set_dup(index, Some(&text.join(" / ")));
             }
         }
     }
@@ -355,18 +1066,18 @@ impl wita::EventHandler for Box<Application> {
 
     fn drop_files(&mut self, _: &wita::Window, paths: &[&std::path::Path], _: wita::PhysicalPosition<f32>) {
         if paths[0].file_name().and_then(|f| f.to_str()) == Some("key_map.txt") {
-            if let Ok(mut data) = OldKeyMap::from_file(&paths[0]) {
+            if let Ok(mut data) = OldKeyMap::from_file(&paths[0], self.shortcut_list.layout()) {
                 for category in 0..self.key_table.category_len() {
                     for index in 0..self.key_table[category].items.len() {
                         let item = &self.key_table[category].items[index];
                         if let Some(src) = data.0.iter_mut().find(|src| src.id == item.id) {
-                            self.update_keys(category, index, src.keys.take());
+                            self.update_keys(category, index, src.keys.take().map(Into::into).unwrap_or_default());
                         } else {
-                            self.update_keys(category, index, None);
+                            self.update_keys(category, index, Bindings::new());
                         }
                     }
                 }
-                self.key_table.to_file("key_map.json").ok();
+                self.save();
                 self.update_shortcut_list();
                 log::debug!("load key_map.txt");
             }
@@ -384,6 +1095,8 @@ impl wita::EventHandler for Box<Application> {
 
 pub const WM_KEY_CONFIG_EDIT_APPLY: u32 = WM_APP + 10;
 pub const WM_KEY_CONFIG_EDIT_CANCEL: u32 = WM_APP + 11;
+/// Posted by MMAccel when it has written the current profile.
+pub const WM_KEY_CONFIG_RELOAD: u32 = WM_APP + 12;
 
 unsafe extern "system" fn main_window_proc(
     hwnd: HWND,
@@ -403,21 +1116,17 @@ unsafe extern "system" fn main_window_proc(
                         let nlv = (lparam.0 as *const NMLISTVIEW).as_ref().unwrap();
                         if app.editor.is_visible() {
                             if let Some(ret) = app.editor.end() {
-                                app.update_keys_to_file(ret.category, ret.item, Some(ret.keys));
+                                app.apply_edit(ret);
                             }
                         }
                         if nlv.uNewState & LVIS_SELECTED != 0 {
-                            app.shortcut_list.clear();
-                            for item in app.key_table[app.side_menu.current_index()].items.iter() {
-                                app.shortcut_list.push(&item.name, &item.keys);
-                            }
-                            app.update_shortcut_list();
+                            app.reload_shortcut_list();
                         }
                     }
                     NM_SETFOCUS => {
                         if app.editor.is_visible() {
                             if let Some(ret) = app.editor.end() {
-                                app.update_keys_to_file(ret.category, ret.item, Some(ret.keys));
+                                app.apply_edit(ret);
                             }
                         }
                     }
@@ -449,18 +1158,14 @@ unsafe extern "system" fn main_window_proc(
                     NM_CLICK => {
                         if app.editor.is_visible() {
                             if let Some(ret) = app.editor.end() {
-                                app.update_keys_to_file(ret.category, ret.item, Some(ret.keys));
+                                app.apply_edit(ret);
                             }
                         }
                     }
                     NM_DBLCLK => {
                         let nia = (lparam.0 as *const NMITEMACTIVATE).as_ref().unwrap();
                         if nia.iItem != -1 {
-                            if let Some(rc) = app.shortcut_list.keys_rect(nia.iItem as _) {
-                                let category = app.side_menu.current_index();
-                                let item = nia.iItem as _;
-                                app.editor.begin(&rc, category, item, app.key_table.get(category, item));
-                            }
+                            app.begin_edit(app.side_menu.current_index(), nia.iItem as _, 0, false);
                         }
                     }
                     NM_RCLICK => {
@@ -473,10 +1178,18 @@ unsafe extern "system" fn main_window_proc(
                                 y: nia.ptAction.y,
                             };
                             ClientToScreen(app.shortcut_list.handle(), &mut pt);
+                            let category = app.side_menu.current_index();
+                            let item = nia.iItem as usize;
+                            let keys = app.key_table[category].items.get(item).map(|i| i.keys.clone());
+                            let repeat = (item < app.key_table[category].items.len())
+                                .then(|| app.key_table.repeat(category, item))
+                                .unwrap_or_default();
                             app.popup_menu.track(
                                 &app.main_window,
-                                app.side_menu.current_index(),
-                                nia.iItem as _,
+                                category,
+                                item,
+                                &keys.unwrap_or_default(),
+                                repeat,
                                 wita::ScreenPosition::new(pt.x, pt.y),
                             );
                         }
@@ -485,7 +1198,7 @@ unsafe extern "system" fn main_window_proc(
                         let lbutton = (GetKeyState(VK_LBUTTON.0 as _) & 0x80) != 0;
                         if app.editor.is_visible() && lbutton {
                             if let Some(ret) = app.editor.end() {
-                                app.update_keys_to_file(ret.category, ret.item, Some(ret.keys));
+                                app.apply_edit(ret);
                             }
                         }
                     }
@@ -507,15 +1220,54 @@ unsafe extern "system" fn main_window_proc(
             LRESULT(1)
         }
         WM_COMMAND => {
-            if (wparam.0 & 0xffff) as u32 == IDM_MENU_DETACH {
-                app.update_keys_to_file(app.popup_menu.category(), app.popup_menu.item(), None);
+            let category = app.popup_menu.category();
+            let item = app.popup_menu.item();
+            match (wparam.0 & 0xffff) as u32 {
+                IDM_MENU_DETACH => {
+                    app.update_keys_to_file(category, item, Bindings::new());
+                }
+                IDM_MENU_ASSIGN_SIDED => app.begin_edit(category, item, 0, true),
+                IDM_MENU_ADD => {
+                    let index = app.key_table.get(category, item).len();
+                    app.begin_edit(category, item, index, false);
+                }
+                IDM_MENU_REPEAT_OFF => app.update_repeat(category, item, Repeat::Off),
+                IDM_MENU_REPEAT_OS => app.update_repeat(category, item, Repeat::Os),
+                IDM_MENU_REPEAT_CUSTOM => app.input_repeat(category, item),
+                id @ IDM_PROFILE_NEW..=IDM_PROFILE_DELETE => app.profile_command(id),
+                id @ IDM_CONTEXT_SELECT..=IDM_CONTEXT_LAST => {
+                    app.select_scope(EditScope::Context(ContextMenu::context(id)))
+                }
+                id @ IDM_LAYER_NEW..=IDM_LAYER_MODE_LAST => app.layer_command(id),
+                id @ IDM_LAYER_SELECT..=IDM_LAYER_SELECT_LAST => {
+                    if let Some(name) = app.context_menu.layer(id).map(|name| name.to_string()) {
+                        app.select_scope(EditScope::Layer(name));
+                    }
+                }
+                id if id >= IDM_PROFILE_SELECT => app.profile_command(id),
+                id @ IDM_MENU_TRIGGER..=IDM_MENU_TRIGGER_LAST => {
+                    let mut keys = app.key_table.get(category, item).clone();
+                    if let Some((index, trigger)) = menu_trigger(id) {
+                        if let Some(seq) = keys.get(index).map(|seq| seq.with_trigger(trigger)) {
+                            keys.set(index, seq);
+                            app.update_keys_to_file(category, item, keys);
+                        }
+                    }
+                }
+                id if id >= IDM_MENU_DETACH_BINDING => {
+                    let mut keys = app.key_table.get(category, item).clone();
+                    if keys.remove((id - IDM_MENU_DETACH_BINDING) as _).is_some() {
+                        app.update_keys_to_file(category, item, keys);
+                    }
+                }
+                _ => {}
             }
             LRESULT(0)
         }
         WM_KEY_CONFIG_EDIT_APPLY => {
             if app.editor.is_visible() {
                 if let Some(ret) = app.editor.end() {
-                    app.update_keys_to_file(ret.category, ret.item, Some(ret.keys));
+                    app.apply_edit(ret);
                 }
             }
             LRESULT(0)
@@ -524,6 +1276,15 @@ unsafe extern "system" fn main_window_proc(
             app.editor.end();
             LRESULT(0)
         }
+        WM_KEY_CONFIG_RELOAD => {
+            // Applying the edit would write the profile without the changes of MMAccel.
+            app.editor.end();
+            let profile = app.profile.clone();
+            if let Err(e) = app.select_profile(&profile) {
+                log::error!("{}", e);
+            }
+            LRESULT(0)
+        }
         _ => DefSubclassProc(hwnd, msg, wparam, lparam),
     }
 }
@@ -0,0 +1,164 @@
+use crate::*;
+
+pub const IDM_LAYER_NEW: u32 = 30;
+pub const IDM_LAYER_KEY: u32 = 31;
+pub const IDM_LAYER_RENAME: u32 = 32;
+pub const IDM_LAYER_DELETE: u32 = 33;
+pub const IDM_LAYER_MODE: u32 = 34;
+pub const IDM_LAYER_MODE_LAST: u32 = IDM_LAYER_MODE + LayerMode::ALL.len() as u32 - 1;
+pub const IDM_CONTEXT_SELECT: u32 = 900;
+pub const IDM_CONTEXT_LAST: u32 = IDM_CONTEXT_SELECT + KeyContext::ALL.len() as u32;
+pub const IDM_LAYER_SELECT: u32 = 950;
+pub const IDM_LAYER_SELECT_LAST: u32 = 999;
+
+/// The bindings being edited in the main window.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EditScope {
+    Context(Option<KeyContext>),
+    Layer(String),
+}
+
+impl Default for EditScope {
+    #[inline]
+    fn default() -> Self {
+        Self::Context(None)
+    }
+}
+
+#[inline]
+pub fn context_label(context: Option<KeyContext>) -> &'static str {
+    match context {
+        None => tr!("context.none"),
+        Some(KeyContext::Model) => tr!("context.model"),
+        Some(KeyContext::Camera) => tr!("context.camera"),
+        Some(KeyContext::Edit) => tr!("context.edit"),
+    }
+}
+
+#[inline]
+pub fn layer_mode_label(mode: LayerMode) -> &'static str {
+    match mode {
+        LayerMode::Momentary => tr!("layer_mode.momentary"),
+        LayerMode::Toggle => tr!("layer_mode.toggle"),
+        LayerMode::OneShot => tr!("layer_mode.one_shot"),
+    }
+}
+
+#[inline]
+pub fn scope_label(scope: &EditScope) -> String {
+    match scope {
+        EditScope::Context(c) => context_label(*c).to_string(),
+        EditScope::Layer(name) => tr!("layer.label", name),
+    }
+}
+
+/// The menu to choose the context or the layer of the bindings being edited.
+pub struct ContextMenu {
+    menu: HMENU,
+    layout: Layout,
+    layers: Vec<String>,
+}
+
+impl ContextMenu {
+    pub fn new(menu_bar: HMENU, layout: Layout) -> Self {
+        unsafe {
+            let menu = CreatePopupMenu();
+            let text = to_wchar(tr!("scope.menu"));
+            AppendMenuW(menu_bar, MF_POPUP, menu.0 as _, PWSTR(text.as_ptr() as _));
+            Self {
+                menu,
+                layout,
+                layers: vec![],
+            }
+        }
+    }
+
+    /// `layers` are the names and the layers sorted by name.
+    pub fn update(&mut self, window: &wita::Window, current: &EditScope, layers: Vec<(String, &Layer)>) {
+        let checked = |b: bool| if b { MF_STRING | MF_CHECKED } else { MF_STRING };
+        unsafe {
+            while GetMenuItemCount(self.menu) > 0 {
+                DeleteMenu(self.menu, 0, MF_BYPOSITION);
+            }
+            let contexts = std::iter::once(None).chain(KeyContext::ALL.iter().copied().map(Some));
+            for (i, context) in contexts.enumerate() {
+                let text = to_wchar(context_label(context));
+                AppendMenuW(
+                    self.menu,
+                    checked(*current == EditScope::Context(context)),
+                    (IDM_CONTEXT_SELECT + i as u32) as _,
+                    PWSTR(text.as_ptr() as _),
+                );
+            }
+            AppendMenuW(self.menu, MF_SEPARATOR, 0, PWSTR::default());
+            let max_layers = (IDM_LAYER_SELECT_LAST - IDM_LAYER_SELECT + 1) as usize;
+            for (i, (name, layer)) in layers.iter().take(max_layers).enumerate() {
+                let key = if layer.key.is_empty() {
+                    tr!("layer.no_key").to_string()
+                } else {
+                    layer.key.to_string_in(self.layout)
+                };
+                let text = to_wchar(format!("{} ({})", scope_label(&EditScope::Layer(name.clone())), key));
+                AppendMenuW(
+                    self.menu,
+                    checked(*current == EditScope::Layer(name.clone())),
+                    (IDM_LAYER_SELECT + i as u32) as _,
+                    PWSTR(text.as_ptr() as _),
+                );
+            }
+            let text = to_wchar(tr!("layer.menu_add"));
+            AppendMenuW(self.menu, MF_STRING, IDM_LAYER_NEW as _, PWSTR(text.as_ptr() as _));
+            let layer = match current {
+                EditScope::Layer(name) => layers.iter().find(|(n, _)| n == name).map(|(_, layer)| *layer),
+                _ => None,
+            };
+            AppendMenuW(self.menu, MF_SEPARATOR, 0, PWSTR::default());
+            let enabled = |flags: MENU_ITEM_FLAGS| if layer.is_some() { flags } else { flags | MF_GRAYED };
+            for (i, mode) in LayerMode::ALL.iter().enumerate() {
+                let text = to_wchar(layer_mode_label(*mode));
+                AppendMenuW(
+                    self.menu,
+                    enabled(checked(layer.map_or(false, |layer| layer.mode == *mode))),
+                    (IDM_LAYER_MODE + i as u32) as _,
+                    PWSTR(text.as_ptr() as _),
+                );
+            }
+            let items = [
+                (IDM_LAYER_KEY, tr!("layer.menu_key")),
+                (IDM_LAYER_RENAME, tr!("layer.menu_rename")),
+                (IDM_LAYER_DELETE, tr!("layer.menu_delete")),
+            ];
+            for (id, text) in items {
+                let text = to_wchar(text);
+                AppendMenuW(self.menu, enabled(MF_STRING), id as _, PWSTR(text.as_ptr() as _));
+            }
+            DrawMenuBar(HWND(window.raw_handle() as _));
+        }
+        self.layers = layers.into_iter().map(|(name, _)| name).collect();
+    }
+
+    /// The context chosen with `id`, `None` for the bindings without a context.
+    #[inline]
+    pub fn context(id: u32) -> Option<KeyContext> {
+        match id.checked_sub(IDM_CONTEXT_SELECT + 1) {
+            Some(i) => KeyContext::ALL.get(i as usize).copied(),
+            None => None,
+        }
+    }
+
+    /// The name of the layer chosen with `IDM_LAYER_SELECT + index`.
+    #[inline]
+    pub fn layer(&self, id: u32) -> Option<&str> {
+        id.checked_sub(IDM_LAYER_SELECT)
+            .and_then(|i| self.layers.get(i as usize))
+            .map(|name| name.as_str())
+    }
+
+    /// The mode chosen with `IDM_LAYER_MODE + index`.
+    #[inline]
+    pub fn layer_mode(id: u32) -> Option<LayerMode> {
+        id.checked_sub(IDM_LAYER_MODE)
+            .and_then(|i| LayerMode::ALL.get(i as usize))
+            .copied()
+    }
+}
@@ -20,18 +20,26 @@ fn theme_font(hwnd: HWND) -> windows::core::Result<HFONT> {
 pub struct EditResult {
     pub category: usize,
     pub item: usize,
-    pub keys: Keys,
+    pub index: usize,
+    pub keys: KeySequence,
 }
 
+const MAX_STROKES: usize = 2;
+
 pub struct Editor {
     hwnd: HWND,
     font: Option<HFONT>,
     input_keys: Vec<u8>,
+    stroke: Keys,
+    strokes: KeySequence,
+    stroke_done: bool,
+    sided: bool,
+    layout: Layout,
     result: Option<EditResult>,
 }
 
 impl Editor {
-    pub fn new(parent: HWND) -> Result<Box<Self>, Error> {
+    pub fn new(parent: HWND, layout: Layout) -> Result<Box<Self>, Error> {
         let class_name = to_wchar("EDIT");
         unsafe {
             let hwnd = CreateWindowExW(
@@ -56,6 +64,11 @@ impl Editor {
                 hwnd,
                 font: font.ok(),
                 input_keys: vec![0; 256],
+                stroke: Keys::new(),
+                strokes: KeySequence::new(),
+                stroke_done: true,
+                sided: false,
+                layout,
                 result: None,
             });
             SetWindowSubclass(hwnd, Some(proc), 0, editor.as_ref() as *const _ as _);
@@ -64,20 +77,24 @@ impl Editor {
     }
 
     #[inline]
-    pub fn begin(&mut self, rc: &RECT, category: usize, item: usize, keys: &Keys) {
+    pub fn begin(&mut self, rc: &RECT, category: usize, item: usize, index: usize, keys: &KeySequence, sided: bool) {
         unsafe {
             MoveWindow(self.hwnd, rc.left, rc.top, rc.right - rc.left, rc.bottom - rc.top, true);
             ShowWindow(self.hwnd, SW_SHOW);
             SetFocus(self.hwnd);
             let text = if !keys.is_empty() {
-                to_wchar(keys.to_strings().join("+"))
+                to_wchar(keys.to_string_in(self.layout))
             } else {
                 to_wchar("")
             };
             SetWindowTextW(self.hwnd, PWSTR(text.as_ptr() as _));
+            self.strokes.clear();
+            self.stroke_done = true;
+            self.sided = sided;
             self.result = Some(EditResult {
                 category,
                 item,
+                index,
                 keys: keys.clone(),
             });
         }
@@ -88,14 +105,51 @@ impl Editor {
         unsafe {
             SetFocus(GetParent(self.hwnd));
             ShowWindow(self.hwnd, SW_HIDE);
-            self.result.take().and_then(|ret| {
-                (ret.keys != Keys::from_slice(&[VK_SHIFT.0 as u32])
-                    && ret.keys != Keys::from_slice(&[VK_CONTROL.0 as u32]))
-                .then(|| ret)
+            self.result.take().and_then(|mut ret| {
+                if ret.keys.len() > 1 && ret.keys.last().map_or(false, |k| k.is_modifiers_only()) {
+                    ret.keys.pop();
+                }
+                const RESERVED: [VIRTUAL_KEY; 6] =
+                    [VK_SHIFT, VK_LSHIFT, VK_RSHIFT, VK_CONTROL, VK_LCONTROL, VK_RCONTROL];
+                let reserved = RESERVED
+                    .iter()
+                    .any(|vk| ret.keys == Keys::from_slice(&[vk.0 as u32]).into());
+                (!reserved).then(|| ret)
             })
         }
     }
 
+    /// Records a key down, with `mouse` being a mouse button or the wheel that the keyboard state leaves out.
+    fn input(&mut self, mouse: Option<u32>) {
+        get_keyboard_state(&mut self.input_keys);
+        if self.sided {
+            self.stroke.keyboard_state_sided(&self.input_keys);
+        } else {
+            self.stroke.keyboard_state(&self.input_keys);
+        }
+        if let Some(vk) = mouse {
+            self.stroke.push(vk);
+        }
+        if self.stroke.is_empty() {
+            return;
+        }
+        if self.stroke_done {
+            if self.strokes.len() >= MAX_STROKES {
+                self.strokes.clear();
+            }
+            self.strokes.push(self.stroke.clone());
+            self.stroke_done = false;
+        } else if let Some(last) = self.strokes.last_mut() {
+            *last = self.stroke.clone();
+        }
+        let result = self.result.as_mut().unwrap();
+        result.keys = self.strokes.clone();
+        let keys = to_wchar(result.keys.to_string_in(self.layout));
+        unsafe {
+            SetWindowTextW(self.hwnd, PWSTR(keys.as_ptr() as _));
+        }
+    }
+
     #[inline]
     pub fn is_visible(&self) -> bool {
         unsafe { IsWindowVisible(self.hwnd).as_bool() }
@@ -137,12 +191,47 @@ unsafe extern "system" fn proc(
     let editor = (data_ptr as *mut Editor).as_mut().unwrap();
     match msg {
         WM_KEYDOWN | WM_SYSKEYDOWN => {
-            let result = editor.result.as_mut().unwrap();
+            editor.input(None);
+            LRESULT(0)
+        }
+        WM_MBUTTONDOWN => {
+            editor.input(Some(VK_MBUTTON.0 as _));
+            LRESULT(0)
+        }
+        WM_XBUTTONDOWN => {
+            let vk = if (wparam.0 >> 16) as u16 == XBUTTON1.0 as u16 {
+                VK_XBUTTON1
+            } else {
+                VK_XBUTTON2
+            };
+            editor.input(Some(vk.0 as _));
+            LRESULT(1)
+        }
+        WM_MOUSEWHEEL => {
+            let vk = if (wparam.0 >> 16) as u16 as i16 > 0 {
+                WHEEL_UP
+            } else {
+                WHEEL_DOWN
+            };
+            editor.input(Some(vk));
+            // The wheel has no release, so the stroke ends here.
+            editor.stroke_done = true;
+            LRESULT(0)
+        }
+        WM_MBUTTONUP | WM_XBUTTONUP => {
+            editor.stroke_done = true;
+            LRESULT(0)
+        }
+        WM_KEYUP | WM_SYSKEYUP => {
             get_keyboard_state(&mut editor.input_keys);
-            result.keys.keyboard_state(&editor.input_keys);
-            if !result.keys.is_empty() {
-                let keys = to_wchar(result.keys.to_strings().join("+"));
-                SetWindowTextW(editor.hwnd, PWSTR(keys.as_ptr() as _));
+            if editor.sided {
+                editor.stroke.keyboard_state_sided(&editor.input_keys);
+            } else {
+                editor.stroke.keyboard_state(&editor.input_keys);
+            }
+            let pressed = editor.strokes.last().map_or(false, |k| !k.is_modifiers_only());
+            if pressed && (editor.stroke.is_empty() || editor.stroke.is_modifiers_only()) {
+                editor.stroke_done = true;
             }
             LRESULT(0)
         }
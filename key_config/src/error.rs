@@ -1,19 +1,29 @@
+use crate::*;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("{}が見つかりませんでした", .0)]
     FileNotFound(String),
-    #[error("IOエラー: {}", .0)]
     Io(std::io::Error),
-    #[error("エラー {}: (0x{:08x}){}", .1, .0.code().0, .0.message())]
     HResult(windows::core::Error, String),
-    #[error("{}({}:{})にエラーがあります", .1, .0.line(), .0.column())]
     JsonFile(serde_json::Error, String),
-    #[error("データがおかしいです")]
     InvalidData,
-    #[error("ウィンドウを作成できませんでした ({})", .0)]
     Wita(wita::ApiError),
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = match self {
+            Self::FileNotFound(path) => tr!("error.file_not_found", path),
+            Self::Io(e) => tr!("error.io", e),
+            Self::HResult(e, text) => tr!("error.hresult", text, format!("{:08x}", e.code().0), e.message()),
+            Self::JsonFile(e, path) => tr!("error.json_file", path, e.line(), e.column()),
+            Self::InvalidData => tr!("error.invalid_data").to_string(),
+            Self::Wita(e) => tr!("error.window", e),
+        };
+        f.write_str(&text)
+    }
+}
+
 impl Error {
     pub fn file(e: std::io::Error, path: impl AsRef<std::path::Path>) -> Self {
         if e.kind() == std::io::ErrorKind::NotFound {
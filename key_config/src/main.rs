@@ -1,22 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod application;
+mod context_menu;
 mod editor;
 mod error;
+mod name_dialog;
 mod old_key_map;
 mod popup_menu;
+mod profile_menu;
 mod shortcut_list;
 mod side_menu;
 
 use application::*;
+use context_menu::*;
 use editor::*;
 use error::*;
 use key_map::*;
 use log4rs::append::{console, console::ConsoleAppender, file::FileAppender};
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
+use name_dialog::*;
 use old_key_map::OldKeyMap;
 use popup_menu::*;
+use profile_menu::*;
 use shortcut_list::*;
 use side_menu::*;
 use windows::Win32::{
@@ -26,7 +32,7 @@ use windows::Win32::{
 use wrapper::*;
 
 fn error(text: impl AsRef<str>) {
-    message_box(None, text, "MMAccel キー設定", MB_OK | MB_ICONERROR);
+    message_box(None, text, tr!("key_config.title"), MB_OK | MB_ICONERROR);
 }
 
 fn build_logger() -> Result<(), Box<dyn std::error::Error + 'static>> {
@@ -54,7 +60,7 @@ fn build_logger() -> Result<(), Box<dyn std::error::Error + 'static>> {
 
 fn main() {
     if let Err(e) = build_logger() {
-        error(&format!("MMAccel キー設定のログを取れません ({})", e));
+        error(&tr!("key_config.log_error", e));
     }
     std::panic::set_hook(Box::new(|info| {
         let msg = if let Some(location) = info.location() {
@@ -0,0 +1,65 @@
+use crate::*;
+
+/// The dialog template in resource.rc.
+const IDD_NAME: u16 = 200;
+const IDC_NAME_EDIT: i32 = 201;
+const MAX_NAME_LEN: usize = 256;
+
+struct Params {
+    title: Vec<u16>,
+    text: String,
+}
+
+unsafe extern "system" fn dialog_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> isize {
+    match msg {
+        WM_INITDIALOG => {
+            SetWindowLongPtrW(hwnd, GWLP_USERDATA, lparam.0);
+            let params = (lparam.0 as *const Params).as_ref().unwrap();
+            SetWindowTextW(hwnd, PWSTR(params.title.as_ptr() as _));
+            let text = to_wchar(&params.text);
+            SetDlgItemTextW(hwnd, IDC_NAME_EDIT, PWSTR(text.as_ptr() as _));
+            SendDlgItemMessageW(hwnd, IDC_NAME_EDIT, EM_SETSEL, WPARAM(0), LPARAM(-1));
+            let cancel = to_wchar(tr!("dialog.cancel"));
+            SetDlgItemTextW(hwnd, IDCANCEL.0, PWSTR(cancel.as_ptr() as _));
+            1
+        }
+        WM_COMMAND => match (wparam.0 & 0xffff) as i32 {
+            id if id == IDOK.0 => {
+                let params = (GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut Params)
+                    .as_mut()
+                    .unwrap();
+                let mut buffer = vec![0u16; MAX_NAME_LEN];
+                let len = GetDlgItemTextW(hwnd, IDC_NAME_EDIT, PWSTR(buffer.as_mut_ptr()), buffer.len() as _);
+                params.text = String::from_utf16_lossy(&buffer[..len as usize]).trim().to_string();
+                EndDialog(hwnd, IDOK.0 as _);
+                1
+            }
+            id if id == IDCANCEL.0 => {
+                EndDialog(hwnd, IDCANCEL.0 as _);
+                1
+            }
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Asks for a name, returning `None` when canceled.
+pub fn input_name(parent: &wita::Window, title: &str, text: &str) -> Option<String> {
+    let mut params = Params {
+        title: to_wchar(title),
+        text: text.to_string(),
+    };
+    unsafe {
+        let hwnd = HWND(parent.raw_handle() as _);
+        let instance = HINSTANCE(GetWindowLongPtrW(hwnd, GWLP_HINSTANCE));
+        let ret = DialogBoxParamW(
+            instance,
+            PWSTR(IDD_NAME as usize as _),
+            hwnd,
+            Some(dialog_proc),
+            LPARAM(&mut params as *mut Params as _),
+        );
+        (ret == IDOK.0 as isize && !params.text.is_empty()).then(|| params.text)
+    }
+}
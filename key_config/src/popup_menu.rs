@@ -1,29 +1,156 @@
 use crate::*;
 
 pub const IDM_MENU_DETACH: u32 = 10;
+pub const IDM_MENU_ASSIGN_SIDED: u32 = 11;
+pub const IDM_MENU_ADD: u32 = 12;
+pub const IDM_MENU_REPEAT_OFF: u32 = 13;
+pub const IDM_MENU_REPEAT_OS: u32 = 14;
+pub const IDM_MENU_REPEAT_CUSTOM: u32 = 15;
+pub const IDM_MENU_DETACH_BINDING: u32 = 100;
+pub const IDM_MENU_TRIGGER: u32 = 300;
+pub const IDM_MENU_TRIGGER_LAST: u32 = 899;
+
+const TRIGGER_STRIDE: u32 = 8;
+
+#[inline]
+pub fn trigger_label(trigger: Trigger) -> &'static str {
+    match trigger {
+        Trigger::Press => tr!("trigger.press"),
+        Trigger::Tap => tr!("trigger.tap"),
+        Trigger::Hold => tr!("trigger.hold"),
+        Trigger::WhileHeld => tr!("trigger.while_held"),
+        Trigger::DoubleTap => tr!("trigger.double_tap"),
+    }
+}
+
+/// The keys followed by the trigger unless it runs on press.
+pub fn sequence_text(seq: &KeySequence, layout: Layout) -> String {
+    if seq.trigger().is_gesture() {
+        format!("{} ({})", seq.to_string_in(layout), trigger_label(seq.trigger()))
+    } else {
+        seq.to_string_in(layout)
+    }
+}
+
+pub fn bindings_text(keys: &Bindings, layout: Layout) -> String {
+    keys.iter()
+        .map(|seq| sequence_text(seq, layout))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// The binding index and the trigger chosen with an ID from `IDM_MENU_TRIGGER`.
+#[inline]
+pub fn menu_trigger(id: u32) -> Option<(usize, Trigger)> {
+    let n = id.checked_sub(IDM_MENU_TRIGGER)?;
+    let trigger = Trigger::ALL.get((n % TRIGGER_STRIDE) as usize)?;
+    Some(((n / TRIGGER_STRIDE) as usize, *trigger))
+}
 
 pub struct PopupMenu {
     menu: HMENU,
+    layout: Layout,
     category: usize,
     item: usize,
 }
 
 impl PopupMenu {
-    pub fn new() -> Self {
+    pub fn new(layout: Layout) -> Self {
+        Self {
+            menu: HMENU(0),
+            layout,
+            category: 0,
+            item: 0,
+        }
+    }
+
+    #[inline]
+    fn build(&mut self, keys: &Bindings, repeat: Repeat) {
         unsafe {
-            let menu = CreatePopupMenu();
-            let text = to_wchar("解除");
-            AppendMenuW(menu, MF_STRING, IDM_MENU_DETACH as _, PWSTR(text.as_ptr() as _));
-            Self {
-                menu,
-                category: 0,
-                item: 0,
+            if self.menu != HMENU(0) {
+                DestroyMenu(self.menu);
+            }
+            self.menu = CreatePopupMenu();
+            let text = to_wchar(tr!("menu.detach"));
+            AppendMenuW(self.menu, MF_STRING, IDM_MENU_DETACH as _, PWSTR(text.as_ptr() as _));
+            let text = to_wchar(tr!("menu.add"));
+            AppendMenuW(self.menu, MF_STRING, IDM_MENU_ADD as _, PWSTR(text.as_ptr() as _));
+            let text = to_wchar(tr!("menu.assign_sided"));
+            AppendMenuW(
+                self.menu,
+                MF_STRING,
+                IDM_MENU_ASSIGN_SIDED as _,
+                PWSTR(text.as_ptr() as _),
+            );
+            let sub_menu = CreatePopupMenu();
+            let custom = match repeat {
+                Repeat::Custom { delay, interval } => tr!("repeat.custom_with", delay, interval),
+                _ => tr!("repeat.custom").to_string(),
+            };
+            let items = [
+                (
+                    IDM_MENU_REPEAT_OFF,
+                    tr!("repeat.off").to_string(),
+                    repeat == Repeat::Off,
+                ),
+                (IDM_MENU_REPEAT_OS, tr!("repeat.os").to_string(), repeat == Repeat::Os),
+                (IDM_MENU_REPEAT_CUSTOM, custom, matches!(repeat, Repeat::Custom { .. })),
+            ];
+            for (id, text, checked) in items {
+                let text = to_wchar(text);
+                let flags = if checked { MF_STRING | MF_CHECKED } else { MF_STRING };
+                AppendMenuW(sub_menu, flags, id as _, PWSTR(text.as_ptr() as _));
+            }
+            let text = to_wchar(tr!("menu.repeat"));
+            AppendMenuW(self.menu, MF_POPUP, sub_menu.0 as _, PWSTR(text.as_ptr() as _));
+            if !keys.is_empty() {
+                AppendMenuW(self.menu, MF_SEPARATOR, 0, PWSTR::default());
+                for (i, seq) in keys.iter().enumerate() {
+                    let sub_menu = CreatePopupMenu();
+                    for (j, trigger) in Trigger::ALL.iter().enumerate() {
+                        let text = to_wchar(trigger_label(*trigger));
+                        let flags = if *trigger == seq.trigger() {
+                            MF_STRING | MF_CHECKED
+                        } else {
+                            MF_STRING
+                        };
+                        AppendMenuW(
+                            sub_menu,
+                            flags,
+                            (IDM_MENU_TRIGGER + i as u32 * TRIGGER_STRIDE + j as u32) as _,
+                            PWSTR(text.as_ptr() as _),
+                        );
+                    }
+                    let text = to_wchar(tr!("menu.trigger", seq.to_string_in(self.layout)));
+                    AppendMenuW(self.menu, MF_POPUP, sub_menu.0 as _, PWSTR(text.as_ptr() as _));
+                }
+            }
+            if keys.len() > 1 {
+                AppendMenuW(self.menu, MF_SEPARATOR, 0, PWSTR::default());
+                for (i, seq) in keys.iter().enumerate() {
+                    let text = to_wchar(tr!("menu.detach_binding", sequence_text(seq, self.layout)));
+                    AppendMenuW(
+                        self.menu,
+                        MF_STRING,
+                        (IDM_MENU_DETACH_BINDING + i as u32) as _,
+                        PWSTR(text.as_ptr() as _),
+                    );
+                }
             }
         }
     }
 
     #[inline]
-    pub fn track(&mut self, window: &wita::Window, category: usize, item: usize, pt: wita::ScreenPosition) {
+    pub fn track(
+        &mut self,
+        window: &wita::Window,
+        category: usize,
+        item: usize,
+        keys: &Bindings,
+        repeat: Repeat,
+        pt: wita::ScreenPosition,
+    ) {
+        self.build(keys, repeat);
         unsafe {
             self.category = category;
             self.item = item;
@@ -53,7 +180,9 @@ impl PopupMenu {
 impl Drop for PopupMenu {
     fn drop(&mut self) {
         unsafe {
-            DestroyMenu(self.menu);
+            if self.menu != HMENU(0) {
+                DestroyMenu(self.menu);
+            }
         }
     }
 }
@@ -0,0 +1,72 @@
+use crate::*;
+
+pub const IDM_PROFILE_NEW: u32 = 20;
+pub const IDM_PROFILE_DUPLICATE: u32 = 21;
+pub const IDM_PROFILE_RENAME: u32 = 22;
+pub const IDM_PROFILE_DELETE: u32 = 23;
+pub const IDM_PROFILE_SELECT: u32 = 1000;
+
+/// The profile menu in the menu bar of the main window.
+pub struct ProfileMenu {
+    menu: HMENU,
+    names: Vec<String>,
+}
+
+impl ProfileMenu {
+    pub fn new(menu_bar: HMENU) -> Self {
+        unsafe {
+            let menu = CreatePopupMenu();
+            let text = to_wchar(tr!("profile.menu"));
+            AppendMenuW(menu_bar, MF_POPUP, menu.0 as _, PWSTR(text.as_ptr() as _));
+            Self { menu, names: vec![] }
+        }
+    }
+
+    pub fn update(&mut self, window: &wita::Window, names: Vec<String>, current: &str) {
+        unsafe {
+            while GetMenuItemCount(self.menu) > 0 {
+                DeleteMenu(self.menu, 0, MF_BYPOSITION);
+            }
+            for (i, name) in names.iter().enumerate() {
+                let text = to_wchar(Profiles::label(name));
+                let flags = if name == current {
+                    MF_STRING | MF_CHECKED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(
+                    self.menu,
+                    flags,
+                    (IDM_PROFILE_SELECT + i as u32) as _,
+                    PWSTR(text.as_ptr() as _),
+                );
+            }
+            AppendMenuW(self.menu, MF_SEPARATOR, 0, PWSTR::default());
+            let items = [
+                (IDM_PROFILE_NEW, tr!("profile.menu_new")),
+                (IDM_PROFILE_DUPLICATE, tr!("profile.menu_duplicate")),
+                (IDM_PROFILE_RENAME, tr!("profile.menu_rename")),
+                (IDM_PROFILE_DELETE, tr!("profile.menu_delete")),
+            ];
+            for (id, text) in items {
+                let text = to_wchar(text);
+                let flags = if Profiles::is_default(current) && (id == IDM_PROFILE_RENAME || id == IDM_PROFILE_DELETE) {
+                    MF_STRING | MF_GRAYED
+                } else {
+                    MF_STRING
+                };
+                AppendMenuW(self.menu, flags, id as _, PWSTR(text.as_ptr() as _));
+            }
+            DrawMenuBar(HWND(window.raw_handle() as _));
+        }
+        self.names = names;
+    }
+
+    /// The name of the profile chosen with `IDM_PROFILE_SELECT + index`.
+    #[inline]
+    pub fn name(&self, id: u32) -> Option<&str> {
+        id.checked_sub(IDM_PROFILE_SELECT)
+            .and_then(|i| self.names.get(i as usize))
+            .map(|name| name.as_str())
+    }
+}
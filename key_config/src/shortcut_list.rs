@@ -2,6 +2,7 @@ use crate::*;
 
 pub struct ShortcutList {
     hwnd: HWND,
+    layout: Layout,
 }
 
 impl ShortcutList {
@@ -10,6 +11,7 @@ impl ShortcutList {
         pt: impl Into<wita::LogicalPosition<i32>>,
         size: impl Into<wita::LogicalSize<i32>>,
         columns_size: [i32; 2],
+        layout: Layout,
     ) -> Result<Self, Error> {
         let dpi = parent.dpi() as i32;
         let pt = pt.into().to_physical(dpi);
@@ -38,7 +40,7 @@ impl ShortcutList {
                 ex_style | LVS_EX_DOUBLEBUFFER | LVS_EX_GRIDLINES | LVS_EX_FULLROWSELECT | LVS_EX_AUTOSIZECOLUMNS;
             SendMessageW(hwnd, LVM_SETEXTENDEDLISTVIEWSTYLE, WPARAM(0), LPARAM(ex_style as _));
             let cx = columns_size[0] * dpi / 96;
-            let text = to_wchar("機能");
+            let text = to_wchar(tr!("column.name"));
             let column = LVCOLUMNW {
                 mask: LVCF_WIDTH | LVCF_FMT | LVCF_MINWIDTH | LVCF_TEXT,
                 fmt: LVCFMT_LEFT,
@@ -50,7 +52,7 @@ impl ShortcutList {
             };
             SendMessageW(hwnd, LVM_INSERTCOLUMNW, WPARAM(0), LPARAM(&column as *const _ as _));
             let cx = columns_size[1] * dpi / 96;
-            let text = to_wchar("キー");
+            let text = to_wchar(tr!("column.keys"));
             let column = LVCOLUMNW {
                 mask: LVCF_WIDTH | LVCF_FMT | LVCF_MINWIDTH | LVCF_TEXT,
                 fmt: LVCFMT_LEFT,
@@ -62,7 +64,7 @@ impl ShortcutList {
             };
             SendMessageW(hwnd, LVM_INSERTCOLUMNW, WPARAM(1), LPARAM(&column as *const _ as _));
             let cx = size.width as i32 - (columns_size.iter().sum::<i32>() + 5) * dpi / 96;
-            let text = to_wchar("重複");
+            let text = to_wchar(tr!("column.warning"));
             let column = LVCOLUMNW {
                 mask: LVCF_WIDTH | LVCF_FMT | LVCF_TEXT,
                 fmt: LVCFMT_LEFT,
@@ -75,7 +77,7 @@ impl ShortcutList {
             SendMessageW(hwnd, LVM_INSERTCOLUMNW, WPARAM(2), LPARAM(&column as *const _ as _));
             let theme = to_wchar("Explorer");
             SetWindowTheme(hwnd, PWSTR(theme.as_ptr() as _), PWSTR::default()).ok();
-            Ok(Self { hwnd })
+            Ok(Self { hwnd, layout })
         }
     }
 
@@ -114,7 +116,7 @@ impl ShortcutList {
     }
 
     #[inline]
-    pub fn push(&mut self, name: impl AsRef<str>, keys: &Keys) {
+    pub fn push(&mut self, name: impl AsRef<str>, keys: &Bindings) {
         unsafe {
             let name = to_wchar(name.as_ref());
             let item = LVITEMW {
@@ -131,10 +133,10 @@ impl ShortcutList {
     }
 
     #[inline]
-    pub fn set_keys(&mut self, index: usize, keys: &Keys) {
+    pub fn set_keys(&mut self, index: usize, keys: &Bindings) {
         unsafe {
             let text = if !keys.is_empty() {
-                to_wchar(&keys.to_strings().join("+"))
+                to_wchar(bindings_text(keys, self.layout))
             } else {
                 to_wchar("")
             };
@@ -205,6 +207,11 @@ impl ShortcutList {
         }
     }
 
+    #[inline]
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
     #[inline]
     pub fn handle(&self) -> HWND {
         self.hwnd
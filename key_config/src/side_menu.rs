@@ -100,6 +100,13 @@ impl SideMenu {
         }
     }
 
+    #[inline]
+    pub fn clear(&mut self) {
+        unsafe {
+            SendMessageW(self.hwnd, LVM_DELETEALLITEMS, WPARAM(0), LPARAM(0));
+        }
+    }
+
     #[inline]
     pub fn resize(&mut self, position: wita::LogicalPosition<i32>, size: wita::LogicalSize<i32>) {
         unsafe {
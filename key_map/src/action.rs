@@ -0,0 +1,238 @@
+use crate::*;
+use std::collections::BTreeMap;
+
+/// What an action in key_map.json does.
+#[derive(Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ActionKind {
+    /// Selects the first entry of a combo box whose text matches `pattern`.
+    /// `combo` is the ID of a combo item in mmd_map.json or a control ID in hex such as `"1b2"`.
+    ComboSelect { combo: String, pattern: String },
+    /// Runs the item after the checked one of `items`, or the one before if `reverse` is true.
+    Cycle {
+        items: Vec<String>,
+        #[serde(default)]
+        reverse: bool,
+    },
+    /// A control of MMD recorded in learn mode.
+    /// `control` is the control ID in hex, and the others are kept to tell which control it is.
+    Control {
+        control: String,
+        class: String,
+        parent: String,
+        caption: String,
+    },
+    /// A menu item of MMD recorded in learn mode. `path` is the captions joined with `/`.
+    MenuItem { command: u32, path: String },
+}
+
+/// An action defined in key_map.json, which is bound like the items in mmd_map.json.
+#[derive(Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Action {
+    /// The name shown in key_config.
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ActionKind,
+}
+
+impl Action {
+    /// Named by the caption, or by the class and the ID if the caption is empty.
+    pub fn control(id: u32, class: &str, parent: &str, caption: &str) -> Self {
+        let caption = caption.trim();
+        Self {
+            name: if caption.is_empty() {
+                format!("{} {:x}", class, id)
+            } else {
+                caption.to_string()
+            },
+            kind: ActionKind::Control {
+                control: format!("{:x}", id),
+                class: class.to_string(),
+                parent: parent.to_string(),
+                caption: caption.to_string(),
+            },
+        }
+    }
+
+    /// Named by the last caption of `path`, or by the command ID if `path` is empty.
+    pub fn menu_item(command: u32, path: &[String]) -> Self {
+        Self {
+            name: path
+                .last()
+                .filter(|caption| !caption.is_empty())
+                .cloned()
+                .unwrap_or_else(|| format!("menu {}", command)),
+            kind: ActionKind::MenuItem {
+                command,
+                path: path.join("/"),
+            },
+        }
+    }
+}
+
+/// Maps the characters that Windows converts from Shift-JIS differently from JIS X 0208,
+/// so that a name typed with either of them matches.
+#[inline]
+fn normalize(c: char) -> char {
+    match c {
+        '\u{301c}' => '\u{ff5e}',
+        '\u{2016}' => '\u{2225}',
+        '\u{2212}' => '\u{ff0d}',
+        '\u{2014}' => '\u{2015}',
+        '\u{00a2}' => '\u{ffe0}',
+        '\u{00a3}' => '\u{ffe1}',
+        '\u{00ac}' => '\u{ffe2}',
+        c => c,
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` is any characters and `?` is any one character.
+/// The spaces around `text` are ignored.
+pub fn match_pattern(pattern: &str, text: &str) -> bool {
+    let p = pattern.chars().map(normalize).collect::<Vec<_>>();
+    let t = text.trim().chars().map(normalize).collect::<Vec<_>>();
+    let (mut pi, mut ti) = (0, 0);
+    // The last `*` and the position in `text` it has matched up to.
+    let mut star = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+impl KeyMap {
+    #[inline]
+    pub fn action(&self, id: &str) -> Option<&Action> {
+        self.actions.get(id)
+    }
+
+    /// Sorted by ID.
+    #[inline]
+    pub fn actions(&self) -> std::collections::btree_map::Iter<String, Action> {
+        self.actions.iter()
+    }
+
+    #[inline]
+    pub fn insert_action(&mut self, id: impl Into<String>, action: Action) {
+        self.actions.insert(id.into(), action);
+    }
+
+    #[inline]
+    pub fn remove_action(&mut self, id: &str) -> Option<Action> {
+        self.actions.remove(id)
+    }
+
+    /// `"Learned1"`, `"Learned2"` and so on that is neither an action nor `used`.
+    pub fn new_action_id(&self, used: impl Fn(&str) -> bool) -> String {
+        (1..)
+            .map(|i| format!("Learned{}", i))
+            .find(|id| !self.actions.contains_key(id) && !used(id))
+            .unwrap()
+    }
+
+    #[inline]
+    pub fn take_actions(&mut self) -> BTreeMap<String, Action> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_test() {
+        let m: KeyMap = serde_json::from_str(
+            r#"{
+                "version": 8,
+                "bindings": { "SelectCenter": "Ctrl+1" },
+                "actions": {
+                    "SelectCenter": { "name": "センターを選択", "type": "combo_select", "combo": "1b2", "pattern": "センター" },
+                    "FpsCycle": { "name": "FPS切り替え", "type": "cycle", "items": ["MenuViewFPS60", "MenuViewFPSNoLimit"] }
+                }
+            }"#,
+        )
+        .unwrap();
+        let action = m.action("SelectCenter").unwrap();
+        assert!(action.name == "センターを選択");
+        assert!(
+            action.kind
+                == ActionKind::ComboSelect {
+                    combo: "1b2".into(),
+                    pattern: "センター".into()
+                }
+        );
+        assert!(
+            m.action("FpsCycle").unwrap().kind
+                == ActionKind::Cycle {
+                    items: vec!["MenuViewFPS60".into(), "MenuViewFPSNoLimit".into()],
+                    reverse: false
+                }
+        );
+        let value = serde_json::to_value(&m).unwrap();
+        assert!(value["actions"]["SelectCenter"]["type"] == "combo_select");
+        assert!(serde_json::from_value::<KeyMap>(value).unwrap().action("SelectCenter") == Some(action));
+        let e = serde_json::from_str::<KeyMap>(
+            r#"{ "version": 8, "bindings": {}, "actions": { "a": { "name": "a", "type": "hoge" } } }"#,
+        );
+        assert!(e.unwrap_err().to_string().contains("actions: a"));
+    }
+
+    #[test]
+    fn learned_action_test() {
+        let mut m = KeyMap::new();
+        let action = Action::control(0x1b2, "Button", "#32770", " 登録 ");
+        assert!(action.name == "登録");
+        m.insert_action("Learned1", action);
+        let action = Action::control(0x2a0, "Edit", "#32770", "");
+        assert!(action.name == "Edit 2a0");
+        let id = m.new_action_id(|id| id == "Learned2");
+        assert!(id == "Learned3");
+        m.insert_action(id, action);
+        let path = ["表示".to_string(), "ボーン表示".to_string()];
+        let action = Action::menu_item(40100, &path);
+        assert!(action.name == "ボーン表示");
+        assert!(
+            action.kind
+                == ActionKind::MenuItem {
+                    command: 40100,
+                    path: "表示/ボーン表示".into()
+                }
+        );
+        assert!(Action::menu_item(40100, &[]).name == "menu 40100");
+        m.insert_action("Learned2", action);
+        let value = serde_json::to_value(&m).unwrap();
+        assert!(value["actions"]["Learned1"]["type"] == "control");
+        assert!(value["actions"]["Learned1"]["control"] == "1b2");
+        assert!(value["actions"]["Learned2"]["type"] == "menu_item");
+        assert!(serde_json::from_value::<KeyMap>(value).unwrap().action("Learned3") == m.action("Learned3"));
+    }
+
+    #[test]
+    fn match_pattern_test() {
+        assert!(match_pattern("センター", "センター"));
+        assert!(match_pattern("センター", " センター "));
+        assert!(!match_pattern("センター", "センター先"));
+        assert!(match_pattern("右*", "右腕"));
+        assert!(match_pattern("*IK", "右足IK"));
+        assert!(match_pattern("右?IK", "右足IK"));
+        assert!(!match_pattern("右?IK", "右つま先IK"));
+        assert!(match_pattern("*つま*", "右つま先IK"));
+        assert!(match_pattern("*", ""));
+        assert!(!match_pattern("", "a"));
+        // The wave dash typed in key_map.json matches the fullwidth tilde converted from Shift-JIS.
+        assert!(match_pattern("あ\u{301c}", "あ\u{ff5e}"));
+    }
+}
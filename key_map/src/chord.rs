@@ -0,0 +1,73 @@
+use crate::*;
+
+/// Whether `k` in a binding is held by `held`, where a generic modifier is held by either side.
+#[inline]
+fn is_held(k: u32, held: &Keys) -> bool {
+    held.0.contains(&k)
+        || MODIFIERS
+            .iter()
+            .any(|(g, l, r)| g.0 as u32 == k && (held.0.contains(&(l.0 as u32)) || held.0.contains(&(r.0 as u32))))
+}
+
+/// Whether the pressed `vk` is `k` in a binding. `vk` may be generic while `k` is side-specific.
+#[inline]
+fn is_pressed(k: u32, vk: u32) -> bool {
+    k == vk || (modifier_index(k).is_some() && modifier_index(k) == modifier_index(vk) && !is_sided_modifier(vk))
+}
+
+impl Keys {
+    /// Whether all the keys are in `held`.
+    #[inline]
+    pub fn is_held(&self, held: &Keys) -> bool {
+        self.0.iter().all(|&k| is_held(k, held))
+    }
+}
+
+/// Picks the most specific of `candidates` whose keys are all held and which contain the pressed `vk`,
+/// for the keys that do not match any binding exactly such as with a stuck modifier.
+///
+/// `held` should have the side-specific modifiers. The candidate with more keys is chosen first,
+/// then the one with more side-specific modifiers, then the one with the smaller key codes.
+pub fn resolve_chord<'a>(held: &Keys, vk: u32, candidates: impl IntoIterator<Item = &'a Keys>) -> Option<&'a Keys> {
+    candidates
+        .into_iter()
+        .filter(|keys| !keys.is_empty() && keys.is_held(held) && keys.0.iter().any(|&k| is_pressed(k, vk)))
+        .min_by(|a, b| {
+            let sided = |keys: &Keys| keys.0.iter().filter(|&&k| is_sided_modifier(k)).count();
+            b.0.len()
+                .cmp(&a.0.len())
+                .then_with(|| sided(b).cmp(&sided(a)))
+                .then_with(|| a.0.cmp(&b.0))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_chord_test() {
+        let keys = |s: &str| s.parse::<Keys>().unwrap();
+        let candidates = ["Z", "Ctrl+Z", "Ctrl+Shift+Z", "RCtrl+Z", "Ctrl+X", "Shift+Alt+Z"]
+            .iter()
+            .map(|s| keys(s))
+            .collect::<Vec<_>>();
+        let z = VK_Z.0 as u32;
+        let resolve = |held: &str, vk: u32| resolve_chord(&keys(held), vk, &candidates).map(|k| k.to_string());
+        assert!(resolve("LCtrl+Z", z).unwrap() == "Ctrl+Z");
+        assert!(resolve("RCtrl+Z", z).unwrap() == "RCtrl+Z");
+        // A stuck key does not break the chord.
+        assert!(resolve("LCtrl+LShift+Alt+Z", z).unwrap() == "Ctrl+Shift+Z");
+        assert!(resolve("LCtrl+Y+Z", z).unwrap() == "Ctrl+Z");
+        assert!(resolve("Y+Z", z).unwrap() == "Z");
+        // Ties are broken by the key codes regardless of the order of the candidates.
+        let reversed = candidates.iter().rev().collect::<Vec<_>>();
+        let held = keys("LCtrl+LShift+Alt+Z");
+        assert!(resolve_chord(&held, z, reversed).unwrap().to_string() == "Ctrl+Shift+Z");
+        // The pressed key has to be a part of the binding.
+        assert!(resolve("LCtrl+Z", VK_X.0 as u32).is_none());
+        assert!(resolve("LCtrl+Z", VK_CONTROL.0 as u32).unwrap() == "Ctrl+Z");
+        assert!(resolve("X", z).is_none());
+        assert!(resolve_chord(&held, z, &[Keys::new()]).is_none());
+    }
+}
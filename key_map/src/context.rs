@@ -0,0 +1,104 @@
+use crate::*;
+use std::collections::BTreeMap;
+
+/// Where a binding applies. Bindings in an active context take precedence over the bindings without a context.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyContext {
+    /// Model edit mode.
+    Model,
+    /// Camera, light and accessory edit mode.
+    Camera,
+    /// A text box has the focus.
+    Edit,
+}
+
+impl KeyContext {
+    pub const ALL: [Self; 3] = [Self::Model, Self::Camera, Self::Edit];
+
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Camera => "camera",
+            Self::Edit => "edit",
+        }
+    }
+}
+
+impl std::fmt::Display for KeyContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for KeyContext {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|c| c.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown context: {}", s))
+    }
+}
+
+impl KeyMap {
+    #[inline]
+    pub fn context(&self, c: KeyContext) -> Option<&KeyMap> {
+        self.contexts.get(&c)
+    }
+
+    /// Adds an empty key map for `c` if there is none.
+    /// `KeyMap::new` is used because `KeyMap::default()` has the default bindings.
+    #[inline]
+    #[allow(clippy::unwrap_or_default)]
+    pub fn context_mut(&mut self, c: KeyContext) -> &mut KeyMap {
+        self.contexts.entry(c).or_insert_with(KeyMap::new)
+    }
+
+    #[inline]
+    pub fn contexts(&self) -> std::collections::btree_map::Iter<KeyContext, KeyMap> {
+        self.contexts.iter()
+    }
+
+    #[inline]
+    pub fn take_contexts(&mut self) -> BTreeMap<KeyContext, KeyMap> {
+        std::mem::take(&mut self.contexts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_test() {
+        let mut m: KeyMap = serde_json::from_str(
+            r#"{
+                "version": 4,
+                "bindings": { "FrameNext": "Right" },
+                "contexts": {
+                    "camera": { "CameraRegister": "Right" },
+                    "edit": { "KillFocus": "Esc" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(m.get("FrameNext").is_some());
+        assert!(m.context(KeyContext::Camera).unwrap().get("CameraRegister").is_some());
+        assert!(m.context(KeyContext::Model).is_none());
+        m.context_mut(KeyContext::Model)
+            .insert("BoneSelect", "C".parse::<Keys>().unwrap());
+        let value = serde_json::to_value(&m).unwrap();
+        assert!(value["contexts"]["model"]["BoneSelect"] == "C");
+        let m: KeyMap = serde_json::from_value(value).unwrap();
+        assert!(m.contexts().map(|(c, _)| *c).collect::<Vec<_>>() == KeyContext::ALL);
+        let e = serde_json::from_str::<KeyMap>(r#"{ "version": 4, "bindings": {}, "contexts": { "hoge": {} } }"#);
+        assert!(e.unwrap_err().to_string().contains("unknown context: hoge"));
+        let mut m = KeyMap::new();
+        m.context_mut(KeyContext::Edit);
+        assert!(serde_json::to_value(m).unwrap().get("contexts").is_none());
+    }
+}
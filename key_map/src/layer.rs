@@ -0,0 +1,149 @@
+use crate::*;
+use serde::ser::SerializeMap;
+use std::collections::BTreeMap;
+
+/// How the key of a layer turns it on.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerMode {
+    /// On while the key is held.
+    #[default]
+    Momentary,
+    /// On and off with each press.
+    Toggle,
+    /// On until the next action runs.
+    OneShot,
+}
+
+impl LayerMode {
+    pub const ALL: [Self; 3] = [Self::Momentary, Self::Toggle, Self::OneShot];
+}
+
+/// Bindings that take precedence over all the others while the layer is on.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    /// The layer cannot be turned on while the key is empty.
+    pub key: Keys,
+    pub mode: LayerMode,
+    /// Only the bindings are used, not the contexts.
+    pub key_map: KeyMap,
+}
+
+impl Layer {
+    #[inline]
+    pub fn new(key: Keys, mode: LayerMode) -> Self {
+        Self {
+            key,
+            mode,
+            key_map: KeyMap::new(),
+        }
+    }
+
+    pub(crate) fn from_value(value: serde_json::Value) -> Result<Self, String> {
+        let mut obj = match value {
+            serde_json::Value::Object(obj) => obj,
+            _ => return Err("not an object".into()),
+        };
+        let key = match obj.remove("key") {
+            Some(serde_json::Value::String(s)) if s.is_empty() => Keys::new(),
+            Some(key) => serde_json::from_value::<Keys>(key).map_err(|e| format!("key: {}", e))?,
+            None => Keys::new(),
+        };
+        let mode = match obj.remove("mode") {
+            Some(mode) => serde_json::from_value::<LayerMode>(mode).map_err(|e| format!("mode: {}", e))?,
+            None => LayerMode::default(),
+        };
+        let key_map = match obj.remove("bindings") {
+            Some(bindings) => KeyMap::from_bindings_value(bindings).map_err(|e| e.to_string())?,
+            None => KeyMap::new(),
+        };
+        Ok(Self { key, mode, key_map })
+    }
+}
+
+impl serde::Serialize for Layer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("key", &self.key)?;
+        map.serialize_entry("mode", &self.mode)?;
+        map.serialize_entry("bindings", &FlatBindings(&self.key_map))?;
+        map.end()
+    }
+}
+
+impl KeyMap {
+    #[inline]
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.get(name)
+    }
+
+    #[inline]
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.get_mut(name)
+    }
+
+    /// Sorted by name.
+    #[inline]
+    pub fn layers(&self) -> std::collections::btree_map::Iter<String, Layer> {
+        self.layers.iter()
+    }
+
+    #[inline]
+    pub fn insert_layer(&mut self, name: impl Into<String>, layer: Layer) {
+        self.layers.insert(name.into(), layer);
+    }
+
+    #[inline]
+    pub fn remove_layer(&mut self, name: &str) -> Option<Layer> {
+        self.layers.remove(name)
+    }
+
+    #[inline]
+    pub fn take_layers(&mut self) -> BTreeMap<String, Layer> {
+        std::mem::take(&mut self.layers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_test() {
+        let mut m: KeyMap = serde_json::from_str(
+            r#"{
+                "version": 7,
+                "bindings": { "FrameNext": "Right" },
+                "layers": {
+                    "nudge": { "key": "CapsLock", "bindings": { "BoneMove": "D" } },
+                    "camera": { "key": "Ctrl+Space", "mode": "one_shot", "bindings": {} }
+                }
+            }"#,
+        )
+        .unwrap();
+        let nudge = m.layer("nudge").unwrap();
+        assert!(nudge.key == "CapsLock".parse::<Keys>().unwrap());
+        assert!(nudge.mode == LayerMode::Momentary);
+        assert!(nudge.key_map.get("BoneMove").is_some());
+        let mut keys = "CapsLock+Shift+D".parse::<Keys>().unwrap();
+        keys.remove(&nudge.key);
+        assert!(keys.to_string() == "Shift+D");
+        assert!(m.layer("camera").unwrap().mode == LayerMode::OneShot);
+        assert!(m.layers().map(|(name, _)| name.as_str()).collect::<Vec<_>>() == ["camera", "nudge"]);
+        m.insert_layer("toggle", Layer::new(Keys::new(), LayerMode::Toggle));
+        let value = serde_json::to_value(&m).unwrap();
+        assert!(value["layers"]["nudge"]["bindings"]["BoneMove"] == "D");
+        assert!(value["layers"]["toggle"]["mode"] == "toggle");
+        let mut m: KeyMap = serde_json::from_value(value).unwrap();
+        assert!(m.layer("toggle").unwrap().key.is_empty());
+        m.take_layers();
+        assert!(serde_json::to_value(&m).unwrap().get("layers").is_none());
+        let e = serde_json::from_str::<KeyMap>(
+            r#"{ "version": 7, "bindings": {}, "layers": { "a": { "key": "A", "mode": "hoge" } } }"#,
+        );
+        assert!(e.unwrap_err().to_string().contains("layers: a: mode"));
+    }
+}
@@ -0,0 +1,138 @@
+use windows::Win32::UI::Input::KeyboardAndMouse::*;
+
+/// Keyboard layout used to name the OEM keys as printed on the keycaps.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Jis,
+    UsAnsi,
+    Uk,
+    De,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] = [Layout::Jis, Layout::UsAnsi, Layout::Uk, Layout::De];
+
+    /// Picks the layout from the language ID in the low word of an `HKL`.
+    pub fn from_lang_id(lang_id: u16) -> Option<Self> {
+        match lang_id {
+            0x0411 => Some(Self::Jis),
+            0x0409 => Some(Self::UsAnsi),
+            0x0809 => Some(Self::Uk),
+            0x0407 | 0x0807 | 0x0c07 => Some(Self::De),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn oem_names(&self) -> &'static [(VIRTUAL_KEY, &'static str)] {
+        match self {
+            Self::Jis => &[
+                (VK_OEM_MINUS, "-"),
+                (VK_OEM_PLUS, ";"),
+                (VK_OEM_COMMA, ","),
+                (VK_OEM_PERIOD, "."),
+                (VK_OEM_1, ":"),
+                (VK_OEM_2, "/"),
+                (VK_OEM_3, "@"),
+                (VK_OEM_4, "["),
+                (VK_OEM_5, "\\"),
+                (VK_OEM_6, "]"),
+                (VK_OEM_7, "^"),
+                (VK_OEM_102, "_"),
+            ],
+            Self::UsAnsi => &[
+                (VK_OEM_MINUS, "-"),
+                (VK_OEM_PLUS, "="),
+                (VK_OEM_COMMA, ","),
+                (VK_OEM_PERIOD, "."),
+                (VK_OEM_1, ";"),
+                (VK_OEM_2, "/"),
+                (VK_OEM_3, "`"),
+                (VK_OEM_4, "["),
+                (VK_OEM_5, "\\"),
+                (VK_OEM_6, "]"),
+                (VK_OEM_7, "'"),
+            ],
+            Self::Uk => &[
+                (VK_OEM_MINUS, "-"),
+                (VK_OEM_PLUS, "="),
+                (VK_OEM_COMMA, ","),
+                (VK_OEM_PERIOD, "."),
+                (VK_OEM_1, ";"),
+                (VK_OEM_2, "/"),
+                (VK_OEM_3, "'"),
+                (VK_OEM_4, "["),
+                (VK_OEM_5, "\\"),
+                (VK_OEM_6, "]"),
+                (VK_OEM_7, "#"),
+                (VK_OEM_8, "`"),
+            ],
+            Self::De => &[
+                (VK_OEM_MINUS, "-"),
+                (VK_OEM_PLUS, "+"),
+                (VK_OEM_COMMA, ","),
+                (VK_OEM_PERIOD, "."),
+                (VK_OEM_1, "Ü"),
+                (VK_OEM_2, "#"),
+                (VK_OEM_3, "Ö"),
+                (VK_OEM_4, "ß"),
+                (VK_OEM_5, "^"),
+                (VK_OEM_6, "´"),
+                (VK_OEM_7, "Ä"),
+                (VK_OEM_102, "<"),
+            ],
+        }
+    }
+
+    #[inline]
+    pub fn oem_name(&self, vk: u32) -> Option<&'static str> {
+        self.oem_names()
+            .iter()
+            .find(|(k, _)| k.0 as u32 == vk)
+            .map(|(_, name)| *name)
+    }
+
+    #[inline]
+    pub fn oem_vk(&self, name: &str) -> Option<u32> {
+        let name = name.to_lowercase();
+        self.oem_names()
+            .iter()
+            .find(|(_, n)| n.to_lowercase() == name)
+            .map(|(k, _)| k.0 as u32)
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Jis => "JIS",
+            Self::UsAnsi => "US (ANSI)",
+            Self::Uk => "UK",
+            Self::De => "DE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oem_name_test() {
+        assert!(Layout::Jis.oem_name(VK_OEM_PLUS.0 as _) == Some(";"));
+        assert!(Layout::UsAnsi.oem_name(VK_OEM_PLUS.0 as _) == Some("="));
+        assert!(Layout::UsAnsi.oem_name(VK_OEM_3.0 as _) == Some("`"));
+        assert!(Layout::UsAnsi.oem_name(VK_OEM_102.0 as _).is_none());
+        assert!(Layout::Uk.oem_name(VK_OEM_7.0 as _) == Some("#"));
+        assert!(Layout::De.oem_vk("ö") == Some(VK_OEM_3.0 as _));
+        for layout in Layout::ALL.iter() {
+            for (vk, name) in layout.oem_names() {
+                assert!(layout.oem_vk(name) == Some(vk.0 as u32));
+            }
+        }
+        assert!(Layout::from_lang_id(0x0809) == Some(Layout::Uk));
+    }
+}
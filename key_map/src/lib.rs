@@ -1,10 +1,65 @@
 use serde::ser::SerializeMap;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::BufReader;
 use windows::Win32::UI::Input::KeyboardAndMouse::*;
 
+mod action;
+mod chord;
+mod context;
+mod layer;
+mod layout;
+mod locale;
+mod migration;
+mod overlay;
+mod profile;
+mod repeat;
+mod trigger;
+mod validation;
+
+pub use action::*;
+pub use chord::*;
+pub use context::*;
+pub use layer::*;
+pub use layout::*;
+pub use locale::*;
+pub use migration::*;
+pub use overlay::*;
+pub use profile::*;
+pub use repeat::*;
+pub use trigger::*;
+pub use validation::*;
+
+/// Pseudo key codes for the mouse wheel, outside the range of `GetKeyboardState`.
+pub const WHEEL_UP: u32 = 0x100;
+pub const WHEEL_DOWN: u32 = 0x101;
+
+#[inline]
+pub fn is_wheel(k: u32) -> bool {
+    k == WHEEL_UP || k == WHEEL_DOWN
+}
+
+/// Mouse buttons that can be bound. The left and right buttons are left to MMD.
+#[inline]
+pub fn is_mouse_button(k: u32) -> bool {
+    k == VK_MBUTTON.0 as u32 || k == VK_XBUTTON1.0 as u32 || k == VK_XBUTTON2.0 as u32
+}
+
+/// Names a key with the canonical (JIS) names used in key_map.json.
+#[inline]
 pub fn vk_to_string(k: u32) -> String {
+    vk_to_string_in(k, Layout::Jis)
+}
+
+pub fn vk_to_string_in(k: u32, layout: Layout) -> String {
+    if let Some(name) = layout.oem_name(k) {
+        return name.into();
+    }
+    match k {
+        WHEEL_UP => return "WheelUp".into(),
+        WHEEL_DOWN => return "WheelDown".into(),
+        _ => {}
+    }
     const ZERO: u16 = b'0' as _;
     const Z: u16 = b'Z' as _;
     const NUMPAD0: u16 = VK_NUMPAD0.0 as _;
@@ -12,6 +67,9 @@ pub fn vk_to_string(k: u32) -> String {
     const F1: u16 = VK_F1.0 as _;
     const F24: u16 = VK_F24.0 as _;
     match VIRTUAL_KEY(k as _) {
+        VK_MBUTTON => "MButton".into(),
+        VK_XBUTTON1 => "XButton1".into(),
+        VK_XBUTTON2 => "XButton2".into(),
         VK_ESCAPE => "Esc".into(),
         VK_TAB => "Tab".into(),
         VK_CAPITAL => "CapsLock".into(),
@@ -40,24 +98,12 @@ pub fn vk_to_string(k: u32) -> String {
         VK_MULTIPLY => "Num*".into(),
         VK_DIVIDE => "Num/".into(),
         VK_DECIMAL => "Num.".into(),
-        // VK_LSHIFT => "LShift".into(),
-        // VK_RSHIFT => "RShift".into(),
-        // VK_LCONTROL => "LCtrl".into(),
-        // VK_RCONTROL => "RCtrl".into(),
-        // VK_LMENU => "LAlt".into(),
-        // VK_RMENU => "RAlt".into(),
-        VK_OEM_MINUS => "-".into(),
-        VK_OEM_PLUS => ";".into(),
-        VK_OEM_COMMA => ",".into(),
-        VK_OEM_PERIOD => ".".into(),
-        VK_OEM_1 => ":".into(),
-        VK_OEM_2 => "/".into(),
-        VK_OEM_3 => "@".into(),
-        VK_OEM_4 => "[".into(),
-        VK_OEM_5 => "\\".into(),
-        VK_OEM_6 => "]".into(),
-        VK_OEM_7 => "^".into(),
-        VK_OEM_102 => "_".into(),
+        VK_LSHIFT => "LShift".into(),
+        VK_RSHIFT => "RShift".into(),
+        VK_LCONTROL => "LCtrl".into(),
+        VK_RCONTROL => "RCtrl".into(),
+        VK_LMENU => "LAlt".into(),
+        VK_RMENU => "RAlt".into(),
         l => match l.0 {
             v @ ZERO..=Z => (v as u8 as char).to_string(),
             v @ NUMPAD0..=NUMPAD9 => format!("Num{}", v - VK_NUMPAD0.0),
@@ -67,7 +113,111 @@ pub fn vk_to_string(k: u32) -> String {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug, serde::Serialize, serde::Deserialize)]
+/// Parses a canonical (JIS) key name as written by `vk_to_string`.
+#[inline]
+pub fn string_to_vk(s: &str) -> Option<u32> {
+    string_to_vk_in(s, Layout::Jis)
+}
+
+pub fn string_to_vk_in(s: &str, layout: Layout) -> Option<u32> {
+    if let Some(vk) = layout.oem_vk(s.trim()) {
+        return Some(vk);
+    }
+    let s = s.trim().to_ascii_lowercase();
+    let vk = match s.as_str() {
+        "wheelup" => return Some(WHEEL_UP),
+        "wheeldown" => return Some(WHEEL_DOWN),
+        "mbutton" => VK_MBUTTON,
+        "xbutton1" => VK_XBUTTON1,
+        "xbutton2" => VK_XBUTTON2,
+        "esc" => VK_ESCAPE,
+        "tab" => VK_TAB,
+        "capslock" => VK_CAPITAL,
+        "shift" => VK_SHIFT,
+        "ctrl" => VK_CONTROL,
+        "alt" => VK_MENU,
+        "lshift" => VK_LSHIFT,
+        "rshift" => VK_RSHIFT,
+        "lctrl" => VK_LCONTROL,
+        "rctrl" => VK_RCONTROL,
+        "lalt" => VK_LMENU,
+        "ralt" => VK_RMENU,
+        "backspace" => VK_BACK,
+        "enter" => VK_RETURN,
+        "space" => VK_SPACE,
+        "printscreen" => VK_SNAPSHOT,
+        "pause" => VK_PAUSE,
+        "insert" => VK_INSERT,
+        "delete" => VK_DELETE,
+        "home" => VK_HOME,
+        "end" => VK_END,
+        "pageup" => VK_PRIOR,
+        "pagedown" => VK_NEXT,
+        "up" => VK_UP,
+        "down" => VK_DOWN,
+        "left" => VK_LEFT,
+        "right" => VK_RIGHT,
+        "num+" => VK_ADD,
+        "num-" => VK_SUBTRACT,
+        "num*" => VK_MULTIPLY,
+        "num/" => VK_DIVIDE,
+        "num." => VK_DECIMAL,
+        _ if s.len() == 1 && s.as_bytes()[0].is_ascii_alphanumeric() => {
+            return Some(s.as_bytes()[0].to_ascii_uppercase() as u32)
+        }
+        _ if s.starts_with("num") => {
+            return s[3..]
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n <= 9)
+                .map(|n| VK_NUMPAD0.0 as u32 + n)
+        }
+        _ if s.starts_with('f') => {
+            return s[1..]
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=24).contains(n))
+                .map(|n| VK_F1.0 as u32 + n - 1)
+        }
+        _ if s.starts_with('(') && s.ends_with(')') => {
+            return s[1..s.len() - 1].parse::<u32>().ok().filter(|n| *n < 0x100)
+        }
+        _ => return None,
+    };
+    Some(vk.0 as u32)
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseKeysError(String);
+
+impl std::fmt::Display for ParseKeysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unknown key \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseKeysError {}
+
+/// (generic, left, right) for each modifier, in display order.
+const MODIFIERS: [(VIRTUAL_KEY, VIRTUAL_KEY, VIRTUAL_KEY); 3] = [
+    (VK_CONTROL, VK_LCONTROL, VK_RCONTROL),
+    (VK_SHIFT, VK_LSHIFT, VK_RSHIFT),
+    (VK_MENU, VK_LMENU, VK_RMENU),
+];
+
+#[inline]
+fn modifier_index(k: u32) -> Option<usize> {
+    MODIFIERS
+        .iter()
+        .position(|(g, l, r)| [g, l, r].iter().any(|m| m.0 as u32 == k))
+}
+
+#[inline]
+fn is_sided_modifier(k: u32) -> bool {
+    MODIFIERS.iter().any(|(_, l, r)| l.0 as u32 == k || r.0 as u32 == k)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Keys(Vec<u32>);
 
 impl Keys {
@@ -100,37 +250,142 @@ impl Keys {
 
     #[inline]
     pub fn keyboard_state(&mut self, v: &[u8]) {
-        #[inline]
-        fn is_lr_key(k: u32) -> bool {
-            k == VK_LSHIFT.0 as u32
-                || k == VK_RSHIFT.0 as u32
-                || k == VK_LCONTROL.0 as u32
-                || k == VK_RCONTROL.0 as u32
-                || k == VK_LMENU.0 as u32
-                || k == VK_RMENU.0 as u32
-        }
-
         self.0.clear();
         for (i, k) in v.iter().enumerate() {
-            if (0x07..0xe0).contains(&i) && (k & 0x80) != 0 && !is_lr_key(i as _) {
+            if (0x07..0xe0).contains(&i) && (k & 0x80) != 0 && !is_sided_modifier(i as _) {
                 self.0.push(i as u32);
             }
         }
         self.0.sort_unstable();
     }
 
+    /// Same as `keyboard_state` but records which side of each modifier is held.
+    #[inline]
+    pub fn keyboard_state_sided(&mut self, v: &[u8]) {
+        self.keyboard_state(v);
+        *self = self.side_variants(v).swap_remove(0);
+    }
+
+    /// Returns the keys with each held modifier replaced by its held sides,
+    /// from the most side-specific combination down to `self` itself.
+    pub fn side_variants(&self, v: &[u8]) -> Vec<Keys> {
+        let mut variants = vec![self.0.clone()];
+        for (generic, l, r) in MODIFIERS.iter() {
+            let generic = generic.0 as u32;
+            if !self.0.contains(&generic) {
+                continue;
+            }
+            let sides = [l, r]
+                .iter()
+                .map(|side| side.0 as u32)
+                .filter(|&side| (v[side as usize] & 0x80) != 0)
+                .collect::<Vec<_>>();
+            let mut next = vec![];
+            for keys in variants.iter() {
+                for &side in sides.iter() {
+                    next.push(keys.iter().map(|&k| if k == generic { side } else { k }).collect());
+                }
+                next.push(keys.clone());
+            }
+            variants = next;
+        }
+        variants
+            .sort_by_key(|keys: &Vec<u32>| std::cmp::Reverse(keys.iter().filter(|&&k| is_sided_modifier(k)).count()));
+        variants.into_iter().map(|keys| Keys::from_slice(&keys)).collect()
+    }
+
+    #[inline]
+    pub fn is_sided(&self) -> bool {
+        self.0.iter().any(|&k| is_sided_modifier(k))
+    }
+
+    /// Replaces the side-specific modifiers with the generic ones.
+    pub fn to_generic(&self) -> Keys {
+        let v = self
+            .0
+            .iter()
+            .map(|&k| match modifier_index(k) {
+                Some(i) => MODIFIERS[i].0 .0 as u32,
+                None => k,
+            })
+            .collect::<Vec<_>>();
+        let mut keys = Self::from_slice(&v);
+        keys.0.dedup();
+        keys
+    }
+
+    /// Removes the keys in `other`, such as the key of a layer being held.
+    #[inline]
+    pub fn remove(&mut self, other: &Keys) {
+        self.0.retain(|k| !other.0.contains(k));
+    }
+
+    #[inline]
+    pub fn contains(&self, vk: u32) -> bool {
+        self.0.contains(&vk)
+    }
+
+    #[inline]
+    pub fn is_modifiers_only(&self) -> bool {
+        !self.0.is_empty() && self.0.iter().all(|&k| modifier_index(k).is_some())
+    }
+
     #[inline]
     pub fn vk(&mut self, vk: u32) {
         self.0.clear();
         self.0.push(vk);
     }
 
+    /// Adds a mouse button or the wheel, which `keyboard_state` leaves out.
+    #[inline]
+    pub fn push(&mut self, vk: u32) {
+        if let Err(index) = self.0.binary_search(&vk) {
+            self.0.insert(index, vk);
+        }
+    }
+
+    #[inline]
     pub fn to_strings(&self) -> Vec<String> {
-        let mut v = vec![];
-        for &k in self.0.iter() {
-            v.push(vk_to_string(k));
+        self.to_strings_in(Layout::Jis)
+    }
+
+    pub fn to_strings_in(&self, layout: Layout) -> Vec<String> {
+        let order = |k: u32| modifier_index(k).unwrap_or(MODIFIERS.len());
+        let mut keys = self.0.clone();
+        keys.sort_by_key(|&k| (order(k), k));
+        keys.into_iter().map(|k| vk_to_string_in(k, layout)).collect()
+    }
+
+    #[inline]
+    pub fn to_string_in(&self, layout: Layout) -> String {
+        self.to_strings_in(layout).join("+")
+    }
+
+    pub fn from_str_in(s: &str, layout: Layout) -> Result<Self, ParseKeysError> {
+        // "+" and "Num+" contain the separator, so a '+' right after a separator or "Num" is part of the name.
+        let mut names: Vec<String> = vec![];
+        let mut name = String::new();
+        let mut separated = false;
+        for c in s.chars().filter(|c| !c.is_whitespace()) {
+            if c == '+' && !name.is_empty() && !name.eq_ignore_ascii_case("num") {
+                names.push(std::mem::take(&mut name));
+                separated = true;
+            } else {
+                name.push(c);
+                separated = false;
+            }
+        }
+        if separated {
+            return Err(ParseKeysError(s.trim().to_string()));
         }
-        v
+        if !name.is_empty() {
+            names.push(name);
+        }
+        let v = names
+            .iter()
+            .map(|name| string_to_vk_in(name, layout).ok_or_else(|| ParseKeysError(name.clone())))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_slice(&v))
     }
 
     #[inline]
@@ -154,47 +409,583 @@ impl Default for Keys {
     }
 }
 
-#[derive(Debug)]
-pub struct KeyMap(HashMap<String, Keys>);
+impl std::fmt::Display for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_strings().join("+"))
+    }
+}
+
+impl std::str::FromStr for Keys {
+    type Err = ParseKeysError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_in(s, Layout::Jis)
+    }
+}
+
+impl serde::Serialize for Keys {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Keys {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Keys;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    formatter,
+                    "a string such as \"Ctrl+Z\" or an array of virtual-key codes"
+                )
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut v = Vec::with_capacity(access.size_hint().unwrap_or(0));
+                while let Some(k) = access.next_element()? {
+                    v.push(k);
+                }
+                Ok(Keys::from_slice(&v))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Keys pressed one after another, such as "Ctrl+K Ctrl+C". `trigger` applies to the last stroke.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct KeySequence {
+    strokes: Vec<Keys>,
+    trigger: Trigger,
+}
+
+impl KeySequence {
+    #[inline]
+    pub fn new() -> Self {
+        Self::from_strokes(&[])
+    }
+
+    #[inline]
+    pub fn from_strokes(v: &[Keys]) -> Self {
+        Self {
+            strokes: v.to_vec(),
+            trigger: Trigger::Press,
+        }
+    }
+
+    #[inline]
+    pub fn trigger(&self) -> Trigger {
+        self.trigger
+    }
+
+    #[inline]
+    pub fn set_trigger(&mut self, trigger: Trigger) {
+        self.trigger = trigger;
+    }
+
+    #[inline]
+    pub fn with_trigger(&self, trigger: Trigger) -> Self {
+        Self {
+            strokes: self.strokes.clone(),
+            trigger,
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.strokes.iter().all(|k| k.is_empty())
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.strokes.len()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.strokes.clear();
+    }
+
+    #[inline]
+    pub fn push(&mut self, keys: Keys) {
+        self.strokes.push(keys);
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<Keys> {
+        self.strokes.pop()
+    }
+
+    #[inline]
+    pub fn last(&self) -> Option<&Keys> {
+        self.strokes.last()
+    }
+
+    #[inline]
+    pub fn last_mut(&mut self) -> Option<&mut Keys> {
+        self.strokes.last_mut()
+    }
+
+    #[inline]
+    pub fn is_sided(&self) -> bool {
+        self.strokes.iter().any(|k| k.is_sided())
+    }
+
+    #[inline]
+    pub fn strokes(&self) -> &[Keys] {
+        &self.strokes
+    }
+
+    /// The strokes without the trigger.
+    #[inline]
+    pub fn to_string_in(&self, layout: Layout) -> String {
+        let v = self.strokes.iter().map(|k| k.to_string_in(layout)).collect::<Vec<_>>();
+        v.join(" ")
+    }
+
+    pub fn from_str_in(s: &str, layout: Layout) -> Result<Self, ParseKeysError> {
+        // Strokes are separated by spaces, but "Ctrl + Z" is still one stroke.
+        let mut strokes: Vec<String> = vec![];
+        for token in s.split_whitespace() {
+            match strokes.last_mut() {
+                Some(last)
+                    if token.starts_with('+') || (last.ends_with('+') && Keys::from_str_in(last, layout).is_err()) =>
+                {
+                    last.push_str(token)
+                }
+                _ => strokes.push(token.to_string()),
+            }
+        }
+        let v = strokes
+            .iter()
+            .map(|s| Keys::from_str_in(s, layout))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_strokes(&v))
+    }
+
+    /// Returns every proper prefix, shortest first. A prefix runs on press.
+    pub fn prefixes(&self) -> impl Iterator<Item = KeySequence> + '_ {
+        (1..self.strokes.len()).map(move |n| Self::from_strokes(&self.strokes[..n]))
+    }
+}
+
+impl From<Keys> for KeySequence {
+    #[inline]
+    fn from(src: Keys) -> Self {
+        Self::from_strokes(&[src])
+    }
+}
+
+impl std::fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_in(Layout::Jis))?;
+        if self.trigger.is_gesture() {
+            write!(f, " ({})", self.trigger)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for KeySequence {
+    type Err = ParseKeysError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_in(s, Layout::Jis)
+    }
+}
+
+impl serde::Serialize for KeySequence {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        if self.trigger.is_gesture() {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("keys", &self.to_string_in(Layout::Jis))?;
+            map.serialize_entry("trigger", &self.trigger)?;
+            map.end()
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for KeySequence {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = KeySequence;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    formatter,
+                    "a string such as \"Ctrl+K Ctrl+C\", an array of virtual-key codes or an object with keys and trigger"
+                )
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let keys =
+                    <Keys as serde::Deserialize>::deserialize(serde::de::value::SeqAccessDeserializer::new(access))?;
+                Ok(keys.into())
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut seq: Option<KeySequence> = None;
+                let mut trigger = Trigger::Press;
+                while let Some(key) = access.next_key::<String>()? {
+                    match key.as_str() {
+                        "keys" => seq = Some(access.next_value()?),
+                        "trigger" => trigger = access.next_value()?,
+                        _ => return Err(serde::de::Error::unknown_field(&key, &["keys", "trigger"])),
+                    }
+                }
+                let seq = seq.ok_or_else(|| serde::de::Error::missing_field("keys"))?;
+                Ok(seq.with_trigger(trigger))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// All key sequences bound to one action.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Bindings(Vec<KeySequence>);
+
+impl Bindings {
+    #[inline]
+    pub fn new() -> Self {
+        Self(vec![])
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn contains(&self, seq: &KeySequence) -> bool {
+        self.0.contains(seq)
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&KeySequence> {
+        self.0.get(index)
+    }
+
+    #[inline]
+    pub fn push(&mut self, seq: KeySequence) {
+        if !seq.is_empty() && !self.0.contains(&seq) {
+            self.0.push(seq);
+        }
+    }
+
+    /// Replaces the binding at `index`, or appends it when `index` is past the end.
+    #[inline]
+    pub fn set(&mut self, index: usize, seq: KeySequence) {
+        if index >= self.0.len() {
+            self.push(seq);
+        } else if let Some(i) = self.0.iter().position(|s| s == &seq) {
+            if i != index {
+                self.0.remove(index);
+            }
+        } else {
+            self.0[index] = seq;
+        }
+    }
+
+    #[inline]
+    pub fn remove(&mut self, index: usize) -> Option<KeySequence> {
+        (index < self.0.len()).then(|| self.0.remove(index))
+    }
+
+    #[inline]
+    pub fn to_string_in(&self, layout: Layout) -> String {
+        let v = self.0.iter().map(|seq| seq.to_string_in(layout)).collect::<Vec<_>>();
+        v.join(" | ")
+    }
+
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<KeySequence> {
+        self.0.iter()
+    }
+}
+
+impl From<Keys> for Bindings {
+    #[inline]
+    fn from(src: Keys) -> Self {
+        KeySequence::from(src).into()
+    }
+}
+
+impl From<KeySequence> for Bindings {
+    #[inline]
+    fn from(src: KeySequence) -> Self {
+        let mut bindings = Self::new();
+        bindings.push(src);
+        bindings
+    }
+}
+
+impl std::iter::IntoIterator for Bindings {
+    type Item = KeySequence;
+    type IntoIter = std::vec::IntoIter<KeySequence>;
+
+    #[inline]
+    fn into_iter(self) -> std::vec::IntoIter<KeySequence> {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> std::iter::IntoIterator for &'a Bindings {
+    type Item = &'a KeySequence;
+    type IntoIter = std::slice::Iter<'a, KeySequence>;
+
+    #[inline]
+    fn into_iter(self) -> std::slice::Iter<'a, KeySequence> {
+        self.0.iter()
+    }
+}
+
+impl std::fmt::Display for Bindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.to_string_in(Layout::Jis))
+    }
+}
+
+impl serde::Serialize for Bindings {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        if self.0.len() == 1 {
+            self.0[0].serialize(serializer)
+        } else {
+            serializer.collect_seq(self.0.iter())
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Bindings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        // An element is either a virtual-key code of the old format or a key sequence.
+        enum Element {
+            Vk(u32),
+            Sequence(KeySequence),
+        }
+
+        impl<'de> serde::Deserialize<'de> for Element {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::de::Deserializer<'de>,
+            {
+                struct Visitor;
+
+                impl<'de> serde::de::Visitor<'de> for Visitor {
+                    type Value = Element;
+
+                    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(formatter, "a virtual-key code or a key sequence")
+                    }
+
+                    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        Ok(Element::Vk(v as u32))
+                    }
+
+                    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                    where
+                        E: serde::de::Error,
+                    {
+                        v.parse().map(Element::Sequence).map_err(E::custom)
+                    }
+
+                    fn visit_seq<A>(self, access: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: serde::de::SeqAccess<'de>,
+                    {
+                        let seq = <KeySequence as serde::Deserialize>::deserialize(
+                            serde::de::value::SeqAccessDeserializer::new(access),
+                        )?;
+                        Ok(Element::Sequence(seq))
+                    }
+
+                    fn visit_map<A>(self, access: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: serde::de::MapAccess<'de>,
+                    {
+                        let seq = <KeySequence as serde::Deserialize>::deserialize(
+                            serde::de::value::MapAccessDeserializer::new(access),
+                        )?;
+                        Ok(Element::Sequence(seq))
+                    }
+                }
+
+                deserializer.deserialize_any(Visitor)
+            }
+        }
+
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Bindings;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "a key sequence or an array of key sequences")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                v.parse::<KeySequence>().map(Bindings::from).map_err(E::custom)
+            }
+
+            fn visit_map<A>(self, access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let seq = <KeySequence as serde::Deserialize>::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(access),
+                )?;
+                Ok(seq.into())
+            }
+
+            fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut vks = vec![];
+                let mut bindings = Bindings::new();
+                while let Some(elem) = access.next_element()? {
+                    match elem {
+                        Element::Vk(vk) => vks.push(vk),
+                        Element::Sequence(seq) => bindings.push(seq),
+                    }
+                }
+                match (vks.is_empty(), bindings.is_empty()) {
+                    (true, _) => Ok(bindings),
+                    (false, true) => Ok(Keys::from_slice(&vks).into()),
+                    (false, false) => Err(serde::de::Error::custom(
+                        "virtual-key codes and key sequences cannot be mixed",
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// The bindings without a context and the bindings for each `KeyContext`.
+#[derive(Clone, Debug)]
+pub struct KeyMap {
+    bindings: HashMap<String, Bindings>,
+    contexts: BTreeMap<KeyContext, KeyMap>,
+    repeats: BTreeMap<String, Repeat>,
+    layers: BTreeMap<String, Layer>,
+    actions: BTreeMap<String, Action>,
+}
 
 impl KeyMap {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            bindings: HashMap::new(),
+            contexts: BTreeMap::new(),
+            repeats: BTreeMap::new(),
+            layers: BTreeMap::new(),
+            actions: BTreeMap::new(),
+        }
     }
 
+    /// Reads a key_map.json of any known version without rewriting it. See also `load`.
     pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
         let file = File::open(path)?;
         Ok(serde_json::from_reader(BufReader::new(file))?)
     }
 
+    /// Reads the `bindings` object of the current format.
+    fn from_bindings_value(value: serde_json::Value) -> serde_json::Result<Self> {
+        serde_json::from_value::<FlatKeyMap>(value).map(|m| m.0)
+    }
+
     #[inline]
-    pub fn insert(&mut self, k: impl AsRef<str>, v: Keys) {
-        self.0.insert(k.as_ref().into(), v);
+    pub fn insert(&mut self, k: impl AsRef<str>, v: impl Into<Bindings>) {
+        self.bindings.insert(k.as_ref().into(), v.into());
     }
 
     #[inline]
-    pub fn get(&self, k: impl AsRef<str>) -> Option<&Keys> {
-        self.0.get(k.as_ref())
+    pub fn get(&self, k: impl AsRef<str>) -> Option<&Bindings> {
+        self.bindings.get(k.as_ref())
     }
 
     #[inline]
-    pub fn get_mut(&mut self, k: impl AsRef<str>) -> Option<&mut Keys> {
-        self.0.get_mut(k.as_ref())
+    pub fn get_mut(&mut self, k: impl AsRef<str>) -> Option<&mut Bindings> {
+        self.bindings.get_mut(k.as_ref())
     }
 
     #[inline]
-    pub fn iter(&self) -> std::collections::hash_map::Iter<String, Keys> {
-        self.0.iter()
+    pub fn iter(&self) -> std::collections::hash_map::Iter<String, Bindings> {
+        self.bindings.iter()
     }
 }
 
 impl std::iter::IntoIterator for KeyMap {
-    type Item = (String, Keys);
-    type IntoIter = std::collections::hash_map::IntoIter<String, Keys>;
+    type Item = (String, Bindings);
+    type IntoIter = std::collections::hash_map::IntoIter<String, Bindings>;
 
     #[inline]
-    fn into_iter(self) -> std::collections::hash_map::IntoIter<String, Keys> {
-        self.0.into_iter()
+    fn into_iter(self) -> std::collections::hash_map::IntoIter<String, Bindings> {
+        self.bindings.into_iter()
     }
 }
 
@@ -203,8 +994,63 @@ impl serde::Serialize for KeyMap {
     where
         S: serde::ser::Serializer,
     {
-        let mut map = serializer.serialize_map(Some(self.0.len()))?;
-        for (k, v) in self.0.iter() {
+        struct Contexts<'a>(&'a BTreeMap<KeyContext, KeyMap>);
+
+        impl<'a> serde::Serialize for Contexts<'a> {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::ser::Serializer,
+            {
+                let v = self
+                    .0
+                    .iter()
+                    .filter(|(_, m)| !m.bindings.is_empty())
+                    .collect::<Vec<_>>();
+                let mut map = serializer.serialize_map(Some(v.len()))?;
+                for (k, v) in v {
+                    map.serialize_entry(k, &FlatBindings(v))?;
+                }
+                map.end()
+            }
+        }
+
+        let has_contexts = self.contexts.values().any(|m| !m.bindings.is_empty());
+        let has_repeats = !self.repeats.is_empty();
+        let has_layers = !self.layers.is_empty();
+        let has_actions = !self.actions.is_empty();
+        let mut map = serializer.serialize_map(Some(
+            2 + has_contexts as usize + has_repeats as usize + has_layers as usize + has_actions as usize,
+        ))?;
+        map.serialize_entry("version", &CURRENT_VERSION)?;
+        map.serialize_entry("bindings", &FlatBindings(self))?;
+        if has_contexts {
+            map.serialize_entry("contexts", &Contexts(&self.contexts))?;
+        }
+        if has_repeats {
+            map.serialize_entry("repeat", &self.repeats)?;
+        }
+        if has_layers {
+            map.serialize_entry("layers", &self.layers)?;
+        }
+        if has_actions {
+            map.serialize_entry("actions", &self.actions)?;
+        }
+        map.end()
+    }
+}
+
+/// The `bindings` object sorted by ID.
+struct FlatBindings<'a>(&'a KeyMap);
+
+impl<'a> serde::Serialize for FlatBindings<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        let mut v = self.0.bindings.iter().collect::<Vec<_>>();
+        v.sort_by(|a, b| a.0.cmp(b.0));
+        let mut map = serializer.serialize_map(Some(v.len()))?;
+        for (k, v) in v {
             map.serialize_entry(k, v)?;
         }
         map.end()
@@ -212,6 +1058,19 @@ impl serde::Serialize for KeyMap {
 }
 
 impl<'de> serde::Deserialize<'de> for KeyMap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let value = <serde_json::Value as serde::Deserialize>::deserialize(deserializer)?;
+        migrate(value).map(|m| m.key_map).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The flat object of bindings used since version 2.
+struct FlatKeyMap(KeyMap);
+
+impl<'de> serde::Deserialize<'de> for FlatKeyMap {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::de::Deserializer<'de>,
@@ -219,7 +1078,7 @@ impl<'de> serde::Deserialize<'de> for KeyMap {
         struct Visitor;
 
         impl<'de> serde::de::Visitor<'de> for Visitor {
-            type Value = KeyMap;
+            type Value = FlatKeyMap;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                 write!(formatter, "KeyMap")
@@ -230,10 +1089,19 @@ impl<'de> serde::Deserialize<'de> for KeyMap {
                 M: serde::de::MapAccess<'de>,
             {
                 let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
-                while let Some((key, value)) = access.next_entry()? {
+                while let Some(key) = access.next_key::<String>()? {
+                    let value = access
+                        .next_value()
+                        .map_err(|e| serde::de::Error::custom(format!("{}: {}", key, e)))?;
                     map.insert(key, value);
                 }
-                Ok(KeyMap(map))
+                Ok(FlatKeyMap(KeyMap {
+                    bindings: map,
+                    contexts: BTreeMap::new(),
+                    repeats: BTreeMap::new(),
+                    layers: BTreeMap::new(),
+                    actions: BTreeMap::new(),
+                }))
             }
         }
 
@@ -243,7 +1111,7 @@ impl<'de> serde::Deserialize<'de> for KeyMap {
 
 impl Default for KeyMap {
     fn default() -> Self {
-        let mut m = Self(HashMap::new());
+        let mut m = Self::new();
         m.insert("Undo", Keys::from_slice(&[VK_CONTROL.0 as _, b'Z' as _]));
         m.insert("Redo", Keys::from_slice(&[VK_CONTROL.0 as _, b'X' as _]));
         m.insert("BoneSelect", Keys::from_slice(&[b'C' as _]));
@@ -303,14 +1171,166 @@ mod tests {
         assert!(vk_to_string(0xdf) == "(223)");
     }
 
+    #[test]
+    fn mouse_keys_test() {
+        let keys = "Ctrl+WheelUp".parse::<Keys>().unwrap();
+        assert!(keys == Keys::from_slice(&[VK_CONTROL.0 as _, WHEEL_UP]));
+        assert!(keys.to_string() == "Ctrl+WheelUp");
+        assert!("xbutton1".parse::<Keys>().unwrap() == Keys::from_slice(&[VK_XBUTTON1.0 as _]));
+        let mut input = vec![0u8; 256];
+        input[VK_SHIFT.0 as usize] = 0x80;
+        input[VK_XBUTTON2.0 as usize] = 0x80;
+        let mut keys = Keys::new();
+        keys.keyboard_state(&input);
+        assert!(keys == Keys::from_slice(&[VK_SHIFT.0 as _]));
+        keys.push(WHEEL_DOWN);
+        keys.push(WHEEL_DOWN);
+        assert!(keys.to_string() == "Shift+WheelDown");
+    }
+
+    #[test]
+    fn keys_string_test() {
+        let keys = Keys::from_slice(&[b'Z' as _, VK_SHIFT.0 as _, VK_CONTROL.0 as _]);
+        assert!(keys.to_string() == "Ctrl+Shift+Z");
+        assert!("ctrl+shift+z".parse::<Keys>().unwrap() == keys);
+        assert!("Ctrl + Num+".parse::<Keys>().unwrap() == Keys::from_slice(&[VK_CONTROL.0 as _, VK_ADD.0 as _]));
+        assert!("Num++Alt".parse::<Keys>().unwrap() == Keys::from_slice(&[VK_MENU.0 as _, VK_ADD.0 as _]));
+        assert!("F12".parse::<Keys>().unwrap() == Keys::from_slice(&[VK_F12.0 as _]));
+        assert!("(223)".parse::<Keys>().unwrap() == Keys::from_slice(&[0xdf]));
+        assert!("Ctrl+Hoge".parse::<Keys>() == Err(ParseKeysError("Hoge".into())));
+        for vk in (0x07..0xe0).filter(|vk| !(0x3a..=0x40).contains(vk)) {
+            let keys = Keys::from_slice(&[vk]);
+            assert!(keys.to_string().parse::<Keys>().unwrap() == keys);
+        }
+    }
+
+    #[test]
+    fn layout_string_test() {
+        let keys = Keys::from_slice(&[VK_CONTROL.0 as _, VK_OEM_PLUS.0 as _]);
+        assert!(keys.to_string() == "Ctrl+;");
+        assert!(keys.to_string_in(Layout::UsAnsi) == "Ctrl+=");
+        assert!(keys.to_string_in(Layout::De) == "Ctrl++");
+        assert!(Keys::from_str_in("Ctrl++", Layout::De).unwrap() == keys);
+        assert!(
+            Keys::from_str_in("ctrl+ü", Layout::De).unwrap() == Keys::from_slice(&[VK_CONTROL.0 as _, VK_OEM_1.0 as _])
+        );
+        assert!(Keys::from_str_in("Ctrl+", Layout::De).is_err());
+        let seq = KeySequence::from_str_in("Ctrl+= Ctrl+Num+", Layout::UsAnsi).unwrap();
+        assert!(seq.to_string() == "Ctrl+; Ctrl+Num+");
+        for layout in Layout::ALL.iter() {
+            for vk in (0x07..0xe0).filter(|vk| !(0x3a..=0x40).contains(vk)) {
+                let keys = Keys::from_slice(&[VK_SHIFT.0 as _, vk]);
+                assert!(Keys::from_str_in(&keys.to_string_in(*layout), *layout).unwrap() == keys);
+            }
+        }
+    }
+
     #[test]
     fn key_map_test() {
-        let mut key_map = KeyMap(HashMap::new());
-        key_map.insert("Undo", Keys(vec![VK_CONTROL.0 as _, b'Z' as _]));
-        key_map.insert("Redo", Keys(vec![VK_CONTROL.0 as _, VK_SHIFT.0 as _, b'Z' as _]));
-        let ret: KeyMap = serde_json::from_str(&serde_json::to_string(&key_map).unwrap()).unwrap();
-        assert!(ret.get("Undo").unwrap() == &Keys(vec![VK_CONTROL.0 as _, b'Z' as _]));
-        assert!(ret.get("Redo").unwrap() == &Keys(vec![VK_CONTROL.0 as _, VK_SHIFT.0 as _, b'Z' as _]));
-        assert!(ret.get("Undo").unwrap() != &Keys(vec![VK_SHIFT.0 as _, b'Z' as _]));
+        let mut key_map = KeyMap::new();
+        key_map.insert("Undo", Keys::from_slice(&[VK_CONTROL.0 as _, b'Z' as _]));
+        key_map.insert(
+            "Redo",
+            Keys::from_slice(&[VK_CONTROL.0 as _, VK_SHIFT.0 as _, b'Z' as _]),
+        );
+        let json = serde_json::to_string(&key_map).unwrap();
+        assert!(json.contains("\"Ctrl+Shift+Z\""));
+        let ret: KeyMap = serde_json::from_str(&json).unwrap();
+        assert!(ret.get("Undo").unwrap() == &Keys::from_slice(&[VK_CONTROL.0 as _, b'Z' as _]).into());
+        assert!(ret.get("Redo").unwrap() == &Keys::from_slice(&[VK_CONTROL.0 as _, VK_SHIFT.0 as _, b'Z' as _]).into());
+        assert!(ret.get("Undo").unwrap() != &Keys::from_slice(&[VK_SHIFT.0 as _, b'Z' as _]).into());
+    }
+
+    #[test]
+    fn key_map_numeric_test() {
+        let ret: KeyMap = serde_json::from_str(r#"{ "Undo": [17, 90], "Redo": "Ctrl+X" }"#).unwrap();
+        assert!(ret.get("Undo").unwrap() == &Keys::from_slice(&[VK_CONTROL.0 as _, b'Z' as _]).into());
+        assert!(ret.get("Redo").unwrap() == &Keys::from_slice(&[VK_CONTROL.0 as _, b'X' as _]).into());
+        let e = serde_json::from_str::<KeyMap>(r#"{ "Undo": "Ctrl+Z", "Redo": "Ctrl+Hoge" }"#).unwrap_err();
+        assert!(e.to_string().starts_with("Redo: unknown key \"Hoge\""));
+    }
+
+    #[test]
+    fn key_sequence_test() {
+        let ctrl_k = Keys::from_slice(&[VK_CONTROL.0 as _, b'K' as _]);
+        let ctrl_c = Keys::from_slice(&[VK_CONTROL.0 as _, b'C' as _]);
+        let seq = KeySequence::from_strokes(&[ctrl_k.clone(), ctrl_c.clone()]);
+        assert!(seq.to_string() == "Ctrl+K Ctrl+C");
+        assert!("ctrl+k  ctrl+c".parse::<KeySequence>().unwrap() == seq);
+        assert!("Ctrl + K Ctrl+ C".parse::<KeySequence>().unwrap() == seq);
+        assert!("Num+ Ctrl+C".parse::<KeySequence>().unwrap().len() == 2);
+        assert!(seq.prefixes().collect::<Vec<_>>() == vec![KeySequence::from(ctrl_k)]);
+        let ret: KeyMap = serde_json::from_str(r#"{ "Undo": "Ctrl+K Ctrl+C" }"#).unwrap();
+        assert!(ret.get("Undo").unwrap() == &seq.into());
+    }
+
+    #[test]
+    fn trigger_test() {
+        let ret: KeyMap = serde_json::from_str(
+            r#"{
+                "MenuViewHalfTransparency": ["V", { "keys": "V", "trigger": "while_held" }],
+                "Undo": { "keys": "Ctrl+K Z", "trigger": "double_tap" }
+            }"#,
+        )
+        .unwrap();
+        let v = "V".parse::<KeySequence>().unwrap();
+        let bindings = ret.get("MenuViewHalfTransparency").unwrap();
+        assert!(bindings.len() == 2);
+        assert!(bindings.get(0).unwrap().trigger() == Trigger::Press);
+        assert!(bindings.get(1).unwrap() == &v.with_trigger(Trigger::WhileHeld));
+        let undo = ret.get("Undo").unwrap().get(0).unwrap();
+        assert!(undo.to_string() == "Ctrl+K Z (double_tap)");
+        assert!(undo.prefixes().all(|p| p.trigger() == Trigger::Press));
+        let json = serde_json::to_value(&ret).unwrap();
+        assert!(
+            json["bindings"]["MenuViewHalfTransparency"]
+                == serde_json::json!(["V", { "keys": "V", "trigger": "while_held" }])
+        );
+        assert!(serde_json::from_value::<KeyMap>(json).unwrap().get("Undo") == ret.get("Undo"));
+        assert!(serde_json::from_str::<KeyMap>(r#"{ "Undo": { "keys": "Z", "trigger": "hoge" } }"#).is_err());
+        assert!(serde_json::from_str::<KeyMap>(r#"{ "Undo": { "trigger": "tap" } }"#).is_err());
+    }
+
+    #[test]
+    fn side_variants_test() {
+        let mut state = vec![0u8; 256];
+        for k in [VK_CONTROL, VK_RCONTROL, VK_SHIFT, VK_LSHIFT, VK_LEFT] {
+            state[k.0 as usize] = 0x80;
+        }
+        let mut keys = Keys::new();
+        keys.keyboard_state(&state);
+        assert!(keys.to_string() == "Ctrl+Shift+Left");
+        let variants = keys.side_variants(&state);
+        assert!(variants.len() == 4);
+        assert!(variants[0].to_string() == "RCtrl+LShift+Left");
+        assert!(variants[3] == keys);
+        assert!(variants.contains(&"RCtrl+Shift+Left".parse().unwrap()));
+        assert!(variants.contains(&"Ctrl+LShift+Left".parse().unwrap()));
+        assert!(!variants.contains(&"LCtrl+Shift+Left".parse().unwrap()));
+        let mut sided = Keys::new();
+        sided.keyboard_state_sided(&state);
+        assert!(sided == variants[0] && sided.is_sided() && !keys.is_sided());
+        assert!("RCtrl+LShift".parse::<Keys>().unwrap().is_modifiers_only());
+    }
+
+    #[test]
+    fn bindings_test() {
+        let right: KeySequence = "Right".parse().unwrap();
+        let d: KeySequence = "D".parse().unwrap();
+        let ret: KeyMap =
+            serde_json::from_str(r#"{ "FrameNext": ["Right", "D"], "Undo": [[17, 90], "Ctrl+Z"] }"#).unwrap();
+        let bindings = ret.get("FrameNext").unwrap();
+        assert!(bindings.len() == 2 && bindings.contains(&right) && bindings.contains(&d));
+        assert!(ret.get("Undo").unwrap().len() == 1);
+        assert!(serde_json::to_string(bindings).unwrap() == r#"["Right","D"]"#);
+        assert!(serde_json::to_string(&Bindings::from(d.clone())).unwrap() == r#""D""#);
+        assert!(serde_json::from_str::<Bindings>(r#"[17, "D"]"#).is_err());
+        let mut bindings = bindings.clone();
+        bindings.set(0, d.clone());
+        assert!(bindings == Bindings::from(d.clone()));
+        bindings.set(1, right.clone());
+        assert!(bindings.to_string() == "D | Right");
+        assert!(bindings.remove(0) == Some(d));
+        assert!(bindings == Bindings::from(right));
     }
 }
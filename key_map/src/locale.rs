@@ -0,0 +1,173 @@
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+
+/// The language of the UI text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    Ja,
+    En,
+}
+
+impl Language {
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ja => "ja",
+            Self::En => "en",
+        }
+    }
+}
+
+impl std::fmt::Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ja" => Ok(Self::Ja),
+            "en" => Ok(Self::En),
+            _ => Err(format!("unknown language: {}", s)),
+        }
+    }
+}
+
+const JA: &str = include_str!("../locale/ja.json");
+const EN: &str = include_str!("../locale/en.json");
+
+/// The UI messages, the category names and the action names in a language.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    messages: HashMap<String, String>,
+    /// The category names in order.json to the names shown.
+    #[serde(default)]
+    categories: HashMap<String, String>,
+    /// The action IDs to the names shown instead of the names in mmd_map.json.
+    #[serde(default)]
+    actions: HashMap<String, String>,
+}
+
+impl Catalog {
+    #[inline]
+    pub fn builtin(language: Language) -> Self {
+        let src = match language {
+            Language::Ja => JA,
+            Language::En => EN,
+        };
+        serde_json::from_str(src).unwrap()
+    }
+
+    /// The built-in catalog with the entries of `dir/ja.json` or `dir/en.json` over it.
+    pub fn load(dir: impl AsRef<std::path::Path>, language: Language) -> std::io::Result<Self> {
+        let mut catalog = Self::builtin(language);
+        let path = dir.as_ref().join(format!("{}.json", language));
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(catalog),
+            Err(e) => return Err(e),
+        };
+        let other: Self = serde_json::from_reader(std::io::BufReader::new(file))?;
+        catalog.messages.extend(other.messages);
+        catalog.categories.extend(other.categories);
+        catalog.actions.extend(other.actions);
+        Ok(catalog)
+    }
+
+    /// `key` itself if the message is missing.
+    #[inline]
+    pub fn text<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages.get(key).map_or(key, |s| s.as_str())
+    }
+
+    /// Replaces each `{}` in the message with `args` in order.
+    pub fn format(&self, key: &str, args: &[&dyn std::fmt::Display]) -> String {
+        let mut pieces = self.text(key).split("{}");
+        let mut s = pieces.next().unwrap_or_default().to_string();
+        let mut args = args.iter();
+        for piece in pieces {
+            if let Some(arg) = args.next() {
+                s.push_str(&arg.to_string());
+            }
+            s.push_str(piece);
+        }
+        s
+    }
+
+    /// `name` in mmd_map.json if the action is missing.
+    #[inline]
+    pub fn action<'a>(&'a self, id: &str, name: &'a str) -> &'a str {
+        self.actions.get(id).map_or(name, |s| s.as_str())
+    }
+
+    #[inline]
+    pub fn category<'a>(&'a self, name: &'a str) -> &'a str {
+        self.categories.get(name).map_or(name, |s| s.as_str())
+    }
+}
+
+static CATALOG: OnceCell<Catalog> = OnceCell::new();
+
+/// Sets the catalog used by `tr!`. This has to be called before any text is looked up.
+/// Returns `false` if the catalog has already been set.
+#[inline]
+pub fn set_catalog(catalog: Catalog) -> bool {
+    CATALOG.set(catalog).is_ok()
+}
+
+/// The Japanese catalog unless `set_catalog` has been called.
+#[inline]
+pub fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| Catalog::builtin(Language::Ja))
+}
+
+/// Looks up a message in the current catalog, replacing each `{}` with the arguments.
+#[macro_export]
+macro_rules! tr {
+    ($key:expr) => {
+        $crate::catalog().text($key)
+    };
+    ($key:expr, $($arg:expr),+ $(,)?) => {
+        $crate::catalog().format($key, &[$(&$arg as &dyn std::fmt::Display),+])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_test() {
+        let ja = Catalog::builtin(Language::Ja);
+        let en = Catalog::builtin(Language::En);
+        for (key, text) in ja.messages.iter() {
+            let ja_args = text.matches("{}").count();
+            assert!(
+                en.messages.get(key).map(|s| s.matches("{}").count()) == Some(ja_args),
+                "{}",
+                key
+            );
+        }
+        assert!(ja.messages.len() == en.messages.len());
+        assert!(en.format("menu.trigger", &[&"V"]) == "Trigger of \"V\"");
+        assert!(ja.format("repeat.custom_with", &[&300, &50]) == "一定間隔 (300ms / 50ms)...");
+        assert!(ja.text("hoge") == "hoge");
+        assert!(ja.action("Undo", "元に戻す") == "元に戻す");
+        assert!(en.action("Undo", "元に戻す") == "Undo");
+        assert!(en.category("フレーム操作") == "Frame");
+        assert!("en".parse::<Language>().unwrap() == Language::En);
+        let dir = std::env::temp_dir().join(format!("key_map_catalog_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("en.json"), r#"{ "actions": { "Undo": "Undo!" } }"#).unwrap();
+        let catalog = Catalog::load(&dir, Language::En).unwrap();
+        assert!(catalog.action("Undo", "") == "Undo!" && catalog.action("Redo", "") == "Redo");
+        assert!(Catalog::load(&dir, Language::Ja).unwrap().text("mmaccel.key_config") == "キー設定");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use crate::*;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// The version written by this build.
+///
+/// * 1: key_map.txt
+/// * 2: key_map.json as a flat object of numeric arrays or key names
+/// * 3: key_map.json with `version` and `bindings`
+/// * 4: key_map.json with `contexts` added
+/// * 5: bindings with a `trigger`
+/// * 6: key_map.json with `repeat` added
+/// * 7: key_map.json with `layers` added
+/// * 8: key_map.json with `actions` added
+pub const CURRENT_VERSION: u32 = 8;
+
+/// Upgrades a key map one version, from `index + 2` to `index + 3`.
+const MIGRATIONS: [fn(Value) -> std::io::Result<Value>; 6] =
+    [v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8];
+
+#[inline]
+fn invalid_data(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
+
+fn v2_to_v3(value: Value) -> std::io::Result<Value> {
+    Ok(serde_json::json!({
+        "version": 3,
+        "bindings": value,
+    }))
+}
+
+fn v3_to_v4(mut value: Value) -> std::io::Result<Value> {
+    value["version"] = Value::from(4);
+    Ok(value)
+}
+
+fn v4_to_v5(mut value: Value) -> std::io::Result<Value> {
+    value["version"] = Value::from(5);
+    Ok(value)
+}
+
+fn v5_to_v6(mut value: Value) -> std::io::Result<Value> {
+    value["version"] = Value::from(6);
+    Ok(value)
+}
+
+fn v6_to_v7(mut value: Value) -> std::io::Result<Value> {
+    value["version"] = Value::from(7);
+    Ok(value)
+}
+
+fn v7_to_v8(mut value: Value) -> std::io::Result<Value> {
+    value["version"] = Value::from(8);
+    Ok(value)
+}
+
+/// Reads the lines of key_map.txt such as `Undo = ctrl + z`, returning the lines that could not be read as well.
+pub fn parse_key_map_txt(text: &str, layout: Layout) -> (Vec<(String, Keys)>, Vec<String>) {
+    let mut items = vec![];
+    let mut skipped = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let ss = line.split('=').collect::<Vec<_>>();
+        if ss.len() != 2 {
+            skipped.push(line.to_string());
+            continue;
+        }
+        match Keys::from_str_in(ss[1], layout) {
+            Ok(keys) => items.push((ss[0].trim().to_string(), keys)),
+            Err(_) => skipped.push(line.to_string()),
+        }
+    }
+    (items, skipped)
+}
+
+/// Returns the version of a key_map.json.
+pub fn version_of(value: &Value) -> std::io::Result<u32> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| invalid_data("key_map.json is not an object"))?;
+    match obj.get("version") {
+        Some(Value::Number(n)) if obj.contains_key("bindings") => n
+            .as_u64()
+            .map(|n| n as u32)
+            .ok_or_else(|| invalid_data(format!("invalid version {}", n))),
+        _ => Ok(2),
+    }
+}
+
+/// A key map read from an older format.
+#[derive(Debug)]
+pub struct Migrated {
+    pub key_map: KeyMap,
+    pub from: u32,
+    /// The lines of key_map.txt that could not be read.
+    pub skipped: Vec<String>,
+}
+
+/// Brings a key_map.json of any known version up to `CURRENT_VERSION`.
+pub fn migrate(mut value: Value) -> std::io::Result<Migrated> {
+    let from = version_of(&value)?;
+    if from < 2 {
+        return Err(invalid_data(format!("invalid version {}", from)));
+    }
+    if from > CURRENT_VERSION {
+        return Err(invalid_data(format!(
+            "key_map.json version {} is newer than this version of MMAccel ({})",
+            from, CURRENT_VERSION
+        )));
+    }
+    for step in MIGRATIONS.iter().skip(from as usize - 2) {
+        value = step(value)?;
+    }
+    let bindings = value
+        .as_object_mut()
+        .and_then(|obj| obj.remove("bindings"))
+        .ok_or_else(|| invalid_data("key_map.json has no bindings"))?;
+    let mut key_map = KeyMap::from_bindings_value(bindings).map_err(|e| invalid_data(e.to_string()))?;
+    let contexts = value.as_object_mut().and_then(|obj| obj.remove("contexts"));
+    if let Some(contexts) = contexts {
+        let contexts = match contexts {
+            Value::Object(contexts) => contexts,
+            _ => return Err(invalid_data("contexts is not an object")),
+        };
+        for (c, bindings) in contexts {
+            let context = c.parse::<KeyContext>().map_err(invalid_data)?;
+            *key_map.context_mut(context) =
+                KeyMap::from_bindings_value(bindings).map_err(|e| invalid_data(format!("{}: {}", c, e)))?;
+        }
+    }
+    let repeats = value.as_object_mut().and_then(|obj| obj.remove("repeat"));
+    if let Some(repeats) = repeats {
+        let repeats = match repeats {
+            Value::Object(repeats) => repeats,
+            _ => return Err(invalid_data("repeat is not an object")),
+        };
+        for (id, repeat) in repeats {
+            let repeat =
+                serde_json::from_value::<Repeat>(repeat).map_err(|e| invalid_data(format!("repeat: {}: {}", id, e)))?;
+            key_map.set_repeat(id, repeat);
+        }
+    }
+    let layers = value.as_object_mut().and_then(|obj| obj.remove("layers"));
+    if let Some(layers) = layers {
+        let layers = match layers {
+            Value::Object(layers) => layers,
+            _ => return Err(invalid_data("layers is not an object")),
+        };
+        for (name, layer) in layers {
+            let layer = Layer::from_value(layer).map_err(|e| invalid_data(format!("layers: {}: {}", name, e)))?;
+            key_map.insert_layer(name, layer);
+        }
+    }
+    let actions = value.as_object_mut().and_then(|obj| obj.remove("actions"));
+    if let Some(actions) = actions {
+        let actions = match actions {
+            Value::Object(actions) => actions,
+            _ => return Err(invalid_data("actions is not an object")),
+        };
+        for (id, action) in actions {
+            let action = serde_json::from_value::<Action>(action)
+                .map_err(|e| invalid_data(format!("actions: {}: {}", id, e)))?;
+            key_map.insert_action(id, action);
+        }
+    }
+    Ok(Migrated {
+        key_map,
+        from,
+        skipped: vec![],
+    })
+}
+
+/// Brings key_map.txt up to `CURRENT_VERSION`.
+pub fn migrate_key_map_txt(text: &str, layout: Layout) -> Migrated {
+    let (items, skipped) = parse_key_map_txt(text, layout);
+    let mut key_map = KeyMap::new();
+    for (id, keys) in items {
+        match key_map.get_mut(&id) {
+            Some(bindings) => bindings.push(keys.into()),
+            None => key_map.insert(id, keys),
+        }
+    }
+    Migrated {
+        key_map,
+        from: 1,
+        skipped,
+    }
+}
+
+/// Finds a file name for the copy of the original, such as `key_map.json.v2.bak`.
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut backup = path.with_file_name(format!("{}.v{}.bak", file_name, version));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{}.v{}.{}.bak", file_name, version, n));
+        n += 1;
+    }
+    backup
+}
+
+impl KeyMap {
+    /// Loads key_map.json. An older file is rewritten in the current format after copying the original aside.
+    /// When key_map.json does not exist, key_map.txt in the same folder is converted if there is one.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let migrated = match std::fs::read_to_string(path) {
+            Ok(text) => {
+                let migrated = migrate(serde_json::from_str(&text)?)?;
+                if migrated.from < CURRENT_VERSION {
+                    std::fs::write(backup_path(path, migrated.from), &text)?;
+                }
+                migrated
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let text = std::fs::read_to_string(path.with_file_name("key_map.txt")).map_err(|_| e)?;
+                let migrated = migrate_key_map_txt(&text, Layout::Jis);
+                for line in &migrated.skipped {
+                    log::warn!("key_map.txt: skipped: {}", line);
+                }
+                migrated
+            }
+            Err(e) => return Err(e),
+        };
+        if migrated.from < CURRENT_VERSION {
+            migrated.key_map.to_file(path)?;
+        }
+        Ok(migrated.key_map)
+    }
+
+    pub fn to_file(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_test() {
+        let v2_numeric = serde_json::json!({ "Undo": [0x11, 0x5a] });
+        let v2 = serde_json::json!({ "Undo": "Ctrl+Z", "Redo": ["Ctrl+X", "Ctrl+Y"] });
+        let v3 = serde_json::json!({ "version": 3, "bindings": { "Undo": "Ctrl+Z" } });
+        let future = serde_json::json!({ "version": CURRENT_VERSION + 1, "bindings": {} });
+        let undo = Bindings::from("Ctrl+Z".parse::<Keys>().unwrap());
+        let m = migrate(v2_numeric).unwrap();
+        assert!(m.from == 2 && m.key_map.get("Undo") == Some(&undo));
+        assert!(m.skipped.is_empty());
+        let m = migrate(v2).unwrap();
+        assert!(m.from == 2 && m.key_map.get("Redo").unwrap().len() == 2);
+        let m = migrate(v3).unwrap();
+        assert!(m.from == 3 && m.key_map.get("Undo") == Some(&undo));
+        assert!(migrate(future).is_err());
+        let written = serde_json::to_value(&m.key_map).unwrap();
+        assert!(version_of(&written).unwrap() == CURRENT_VERSION);
+        assert!(migrate(written).unwrap().key_map.get("Undo") == Some(&undo));
+    }
+
+    #[test]
+    fn migrate_key_map_txt_test() {
+        let text = "# comment\nUndo = ctrl + z\nRedo = ctrl + x\nRedo = ctrl + y\nHoge = ctrl + hoge\n";
+        let (items, skipped) = parse_key_map_txt(text, Layout::Jis);
+        assert!(items.len() == 3);
+        assert!(skipped == ["Hoge = ctrl + hoge"]);
+        let m = migrate_key_map_txt(text, Layout::Jis);
+        assert!(m.from == 1);
+        assert!(m.skipped == ["Hoge = ctrl + hoge"]);
+        assert!(m.key_map.get("Redo").unwrap().to_string() == "Ctrl+X | Ctrl+Y");
+    }
+}
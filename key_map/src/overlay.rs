@@ -0,0 +1,201 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// A user file in mmd_map.d, which is written like mmd_map.json.
+/// An item or a category of `null` hides the one in mmd_map.json.
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub file_name: String,
+    categories: Map<String, Value>,
+}
+
+/// An overlay that cannot be read, or an entry of it that conflicts with another.
+#[derive(Debug)]
+pub struct OverlayError {
+    pub file_name: String,
+    pub category: Option<String>,
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: ", self.file_name)?;
+        if let Some(category) = &self.category {
+            write!(f, "{}: ", category)?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "{}: ", id)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for OverlayError {}
+
+impl Overlay {
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+        let categories = serde_json::from_reader(std::io::BufReader::new(file))?;
+        Ok(Self {
+            file_name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            categories,
+        })
+    }
+
+    /// The JSON files in `dir` sorted by name, so that the later ones take precedence.
+    /// Empty if there is no `dir`.
+    pub fn load_dir(dir: impl AsRef<std::path::Path>) -> (Vec<Self>, Vec<OverlayError>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return (vec![], vec![]),
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some(std::ffi::OsStr::new("json")))
+            .collect::<Vec<_>>();
+        paths.sort();
+        let mut overlays = vec![];
+        let mut errors = vec![];
+        for path in paths {
+            match Self::from_file(&path) {
+                Ok(overlay) => overlays.push(overlay),
+                Err(e) => errors.push(OverlayError {
+                    file_name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                    category: None,
+                    id: None,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        (overlays, errors)
+    }
+}
+
+/// Merges `overlays` in order into the categories of mmd_map.json.
+/// An item is replaced even if it conflicts, and the conflicts are returned.
+pub fn merge_overlays(base: &mut Map<String, Value>, overlays: &[Overlay]) -> Vec<OverlayError> {
+    let mut errors = vec![];
+    // The overlay that added or replaced each item.
+    let mut defined = HashMap::<String, &str>::new();
+    for overlay in overlays {
+        let error = |category: &str, id: Option<&str>, message: String| OverlayError {
+            file_name: overlay.file_name.clone(),
+            category: Some(category.to_string()),
+            id: id.map(|id| id.to_string()),
+            message,
+        };
+        for (category, items) in overlay.categories.iter() {
+            let items = match items {
+                Value::Null => {
+                    if base.remove(category).is_none() {
+                        errors.push(error(category, None, "unknown category".to_string()));
+                    }
+                    continue;
+                }
+                Value::Object(items) => items,
+                _ => {
+                    errors.push(error(category, None, "not an object".to_string()));
+                    continue;
+                }
+            };
+            for (id, item) in items.iter() {
+                let current = base
+                    .iter()
+                    .find(|(_, items)| items.get(id).is_some())
+                    .map(|(category, _)| category.clone());
+                if let Some(current) = current.as_ref() {
+                    if let Some(items) = base.get_mut(current).and_then(|items| items.as_object_mut()) {
+                        items.remove(id);
+                    }
+                }
+                if item.is_null() {
+                    if current.is_none() {
+                        errors.push(error(category, Some(id), "unknown id".to_string()));
+                    }
+                    defined.remove(id);
+                    continue;
+                }
+                if let Some(file_name) = defined.insert(id.clone(), &overlay.file_name) {
+                    errors.push(error(category, Some(id), format!("also defined in {}", file_name)));
+                }
+                match current {
+                    Some(current) if &current != category => {
+                        errors.push(error(category, Some(id), format!("moved from {}", current)));
+                    }
+                    _ => {}
+                }
+                let items = base
+                    .entry(category.clone())
+                    .or_insert_with(|| Value::Object(Map::new()));
+                match items.as_object_mut() {
+                    Some(items) => {
+                        items.insert(id.clone(), item.clone());
+                    }
+                    None => errors.push(error(category, Some(id), "the category is not an object".to_string())),
+                }
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlay(file_name: &str, src: &str) -> Overlay {
+        Overlay {
+            file_name: file_name.to_string(),
+            categories: serde_json::from_str(src).unwrap(),
+        }
+    }
+
+    #[test]
+    fn merge_overlays_test() {
+        let mut base: Map<String, Value> = serde_json::from_str(
+            r#"{
+                "編集": { "Undo": [ "元に戻す", "button", "190" ], "Redo": [ "やり直し", "button", "191" ] },
+                "ファイル": { "MenuFileNew": [ "新規", "menu", 0, 0 ] },
+                "ヘルプ": { "MenuHelpAbout": [ "バージョン情報", "menu", 7, 6 ] }
+            }"#,
+        )
+        .unwrap();
+        let overlays = [
+            overlay(
+                "a.json",
+                r#"{
+                    "編集": { "Undo": [ "元に戻す!", "button", "190" ], "Redo": null, "Hoge": null },
+                    "追加": { "MyButton": [ "ボタン", "button", "2a0" ], "MenuFileNew": [ "新規", "menu", 0, 0 ] },
+                    "ヘルプ": null,
+                    "なし": null
+                }"#,
+            ),
+            overlay(
+                "b.json",
+                r#"{ "追加": { "MyButton": [ "ボタン2", "button", "2a1" ] }, "c": 1 }"#,
+            ),
+        ];
+        let errors = merge_overlays(&mut base, &overlays)
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        assert!(base["編集"]["Undo"][0] == "元に戻す!");
+        assert!(base["編集"].get("Redo").is_none());
+        assert!(base["ファイル"].as_object().unwrap().is_empty());
+        assert!(base["追加"]["MyButton"][2] == "2a1");
+        assert!(base["追加"]["MenuFileNew"][0] == "新規");
+        assert!(base.get("ヘルプ").is_none());
+        assert!(
+            errors
+                == [
+                    "a.json: なし: unknown category",
+                    "a.json: 編集: Hoge: unknown id",
+                    "a.json: 追加: MenuFileNew: moved from ファイル",
+                    "b.json: c: not an object",
+                    "b.json: 追加: MyButton: also defined in a.json",
+                ]
+        );
+    }
+}
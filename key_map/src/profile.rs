@@ -0,0 +1,222 @@
+use crate::*;
+use std::path::{Path, PathBuf};
+
+/// The profile saved as key_map.json.
+pub const DEFAULT_PROFILE: &str = "default";
+
+const KEY_MAP_FILE_NAME: &str = "key_map.json";
+const PROFILE_FILE_NAME: &str = "profile.json";
+
+#[inline]
+fn error(kind: std::io::ErrorKind, msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(kind, msg.into())
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CurrentProfile {
+    current: String,
+}
+
+/// Named key maps saved side by side, key_map.json for the default profile and key_map.<name>.json for the others.
+/// The selected profile is kept in profile.json.
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    dir: PathBuf,
+}
+
+impl Profiles {
+    #[inline]
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    #[inline]
+    pub fn is_default(name: &str) -> bool {
+        name.eq_ignore_ascii_case(DEFAULT_PROFILE)
+    }
+
+    /// The name shown in the menus and the title, with the default profile translated.
+    #[inline]
+    pub fn label(name: &str) -> &str {
+        if Self::is_default(name) {
+            tr!("profile.default")
+        } else {
+            name
+        }
+    }
+
+    /// A name has to be usable in a file name.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.trim() == name
+            && !name.starts_with('.')
+            && !name.ends_with('.')
+            && !name.chars().any(|c| c.is_control() || "\\/:*?\"<>|".contains(c))
+            && !Self::is_default(name)
+    }
+
+    /// Returns the profile name when `file_name` is a profile file.
+    pub fn name_of_file(file_name: &str) -> Option<&str> {
+        if file_name == KEY_MAP_FILE_NAME {
+            return Some(DEFAULT_PROFILE);
+        }
+        file_name
+            .strip_prefix("key_map.")
+            .and_then(|s| s.strip_suffix(".json"))
+            .filter(|name| Self::is_valid_name(name))
+    }
+
+    /// Returns `true` when a change of `file_name` affects the profiles.
+    #[inline]
+    pub fn is_profile_file(file_name: &str) -> bool {
+        file_name == PROFILE_FILE_NAME || Self::name_of_file(file_name).is_some()
+    }
+
+    #[inline]
+    pub fn path(&self, name: &str) -> PathBuf {
+        if Self::is_default(name) {
+            self.dir.join(KEY_MAP_FILE_NAME)
+        } else {
+            self.dir.join(format!("key_map.{}.json", name))
+        }
+    }
+
+    /// The default profile first, then the others sorted by name.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = std::fs::read_dir(&self.dir)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let file_name = entry.file_name();
+                        let name = Self::name_of_file(file_name.to_str()?)?;
+                        (!Self::is_default(name)).then(|| name.to_string())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        names
+    }
+
+    #[inline]
+    fn stored_current(&self) -> Option<String> {
+        std::fs::read_to_string(self.dir.join(PROFILE_FILE_NAME))
+            .ok()
+            .and_then(|s| serde_json::from_str::<CurrentProfile>(&s).ok())
+            .map(|p| p.current)
+    }
+
+    /// The selected profile, or the default profile when it has been removed.
+    pub fn current(&self) -> String {
+        self.stored_current()
+            .filter(|name| Self::is_default(name) || (Self::is_valid_name(name) && self.path(name).is_file()))
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    pub fn set_current(&self, name: &str) -> std::io::Result<()> {
+        let data = serde_json::to_string_pretty(&CurrentProfile {
+            current: name.to_string(),
+        })?;
+        std::fs::write(self.dir.join(PROFILE_FILE_NAME), data)
+    }
+
+    fn check_new_name(&self, name: &str) -> std::io::Result<PathBuf> {
+        if !Self::is_valid_name(name) {
+            return Err(error(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid profile name: {}", name),
+            ));
+        }
+        let path = self.path(name);
+        if path.exists() {
+            return Err(error(
+                std::io::ErrorKind::AlreadyExists,
+                format!("profile already exists: {}", name),
+            ));
+        }
+        Ok(path)
+    }
+
+    /// Creates an empty profile.
+    pub fn create(&self, name: &str) -> std::io::Result<()> {
+        let path = self.check_new_name(name)?;
+        KeyMap::new().to_file(path)
+    }
+
+    /// Creates `name` with the bindings of `src`.
+    pub fn duplicate(&self, src: &str, name: &str) -> std::io::Result<()> {
+        let path = self.check_new_name(name)?;
+        KeyMap::load(self.path(src))?.to_file(path)
+    }
+
+    /// The default profile cannot be renamed.
+    pub fn rename(&self, name: &str, new_name: &str) -> std::io::Result<()> {
+        if Self::is_default(name) {
+            return Err(error(
+                std::io::ErrorKind::InvalidInput,
+                "the default profile cannot be renamed",
+            ));
+        }
+        let path = self.check_new_name(new_name)?;
+        std::fs::rename(self.path(name), path)?;
+        if self.stored_current().as_deref() == Some(name) {
+            self.set_current(new_name)?;
+        }
+        Ok(())
+    }
+
+    /// The default profile cannot be deleted. Deleting the selected profile selects the default profile.
+    pub fn delete(&self, name: &str) -> std::io::Result<()> {
+        if Self::is_default(name) {
+            return Err(error(
+                std::io::ErrorKind::InvalidInput,
+                "the default profile cannot be deleted",
+            ));
+        }
+        let current = self.current();
+        std::fs::remove_file(self.path(name))?;
+        if current == name {
+            self.set_current(DEFAULT_PROFILE)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_test() {
+        let dir = std::env::temp_dir().join(format!("mmaccel_profiles_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let profiles = Profiles::new(&dir);
+        let mut m = KeyMap::new();
+        m.insert("Undo", "Ctrl+Z".parse::<Keys>().unwrap());
+        m.to_file(profiles.path(DEFAULT_PROFILE)).unwrap();
+        assert!(profiles.names() == [DEFAULT_PROFILE]);
+        assert!(profiles.current() == DEFAULT_PROFILE);
+        profiles.create("camera").unwrap();
+        profiles.duplicate(DEFAULT_PROFILE, "model").unwrap();
+        assert!(profiles.create("camera").is_err());
+        assert!(profiles.create("a/b").is_err());
+        assert!(profiles.create("Default").is_err());
+        assert!(profiles.names() == [DEFAULT_PROFILE, "camera", "model"]);
+        assert!(KeyMap::load(profiles.path("model")).unwrap().get("Undo").is_some());
+        assert!(KeyMap::load(profiles.path("camera")).unwrap().iter().count() == 0);
+        profiles.set_current("model").unwrap();
+        profiles.rename("model", "modeling").unwrap();
+        assert!(profiles.current() == "modeling");
+        assert!(profiles.rename(DEFAULT_PROFILE, "hoge").is_err());
+        profiles.delete("modeling").unwrap();
+        assert!(profiles.current() == DEFAULT_PROFILE);
+        assert!(profiles.delete(DEFAULT_PROFILE).is_err());
+        assert!(profiles.names() == [DEFAULT_PROFILE, "camera"]);
+        assert!(Profiles::name_of_file("key_map.camera.json") == Some("camera"));
+        assert!(Profiles::name_of_file("key_map.json.v2.bak").is_none());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
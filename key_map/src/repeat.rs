@@ -0,0 +1,170 @@
+use crate::*;
+
+/// What holding the keys of an action does.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Repeat {
+    /// Runs once per press.
+    Off,
+    /// Runs again on every auto-repeat of Windows.
+    #[default]
+    Os,
+    /// Runs again after `delay` and then every `interval` milliseconds, regardless of the keyboard settings.
+    Custom { delay: u32, interval: u32 },
+}
+
+impl Repeat {
+    pub const DEFAULT_DELAY: u32 = 300;
+    pub const DEFAULT_INTERVAL: u32 = 50;
+}
+
+impl std::fmt::Display for Repeat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Os => write!(f, "os"),
+            Self::Custom { delay, interval } => write!(f, "{}ms, {}ms", delay, interval),
+        }
+    }
+}
+
+impl serde::Serialize for Repeat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        match self {
+            Self::Off => serializer.serialize_str("off"),
+            Self::Os => serializer.serialize_str("os"),
+            Self::Custom { delay, interval } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("delay", delay)?;
+                map.serialize_entry("interval", interval)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Repeat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Repeat;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "\"off\", \"os\" or an object with delay and interval")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                match v {
+                    "off" => Ok(Repeat::Off),
+                    "os" => Ok(Repeat::Os),
+                    _ => Err(E::unknown_variant(v, &["off", "os"])),
+                }
+            }
+
+            fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                let mut delay = None;
+                let mut interval = None;
+                while let Some(key) = access.next_key::<String>()? {
+                    match key.as_str() {
+                        "delay" => delay = Some(access.next_value()?),
+                        "interval" => interval = Some(access.next_value()?),
+                        _ => return Err(serde::de::Error::unknown_field(&key, &["delay", "interval"])),
+                    }
+                }
+                let interval: u32 = interval.ok_or_else(|| serde::de::Error::missing_field("interval"))?;
+                if interval == 0 {
+                    return Err(serde::de::Error::custom("interval must be greater than 0"));
+                }
+                Ok(Repeat::Custom {
+                    delay: delay.unwrap_or(Repeat::DEFAULT_DELAY),
+                    interval,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl KeyMap {
+    /// `Repeat::Os` unless it has been set.
+    #[inline]
+    pub fn repeat(&self, id: &str) -> Repeat {
+        self.repeats.get(id).copied().unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn set_repeat(&mut self, id: impl AsRef<str>, repeat: Repeat) {
+        if repeat == Repeat::Os {
+            self.repeats.remove(id.as_ref());
+        } else {
+            self.repeats.insert(id.as_ref().to_string(), repeat);
+        }
+    }
+
+    /// The actions whose repeat is not `Repeat::Os`, sorted by ID.
+    #[inline]
+    pub fn repeats(&self) -> impl Iterator<Item = (&String, &Repeat)> {
+        self.repeats.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeat_test() {
+        let m: KeyMap = serde_json::from_str(
+            r#"{
+                "version": 6,
+                "bindings": { "Undo": "Ctrl+Z", "FrameNext": "Right" },
+                "repeat": { "Undo": "off", "FrameNext": { "delay": 200, "interval": 33 } }
+            }"#,
+        )
+        .unwrap();
+        assert!(m.repeat("Undo") == Repeat::Off);
+        assert!(
+            m.repeat("FrameNext")
+                == Repeat::Custom {
+                    delay: 200,
+                    interval: 33
+                }
+        );
+        assert!(m.repeat("Redo") == Repeat::Os);
+        let json = serde_json::to_value(&m).unwrap();
+        assert!(json["repeat"]["Undo"] == "off");
+        let mut m = serde_json::from_value::<KeyMap>(json).unwrap();
+        assert!(
+            m.repeat("FrameNext")
+                == Repeat::Custom {
+                    delay: 200,
+                    interval: 33
+                }
+        );
+        m.set_repeat("Undo", Repeat::Os);
+        m.set_repeat("FrameNext", Repeat::Os);
+        assert!(m.repeats().count() == 0);
+        assert!(serde_json::to_value(&m).unwrap().get("repeat").is_none());
+        let e = serde_json::from_str::<KeyMap>(r#"{ "version": 6, "bindings": {}, "repeat": { "Undo": "hoge" } }"#);
+        assert!(e.is_err());
+        let e = serde_json::from_str::<KeyMap>(
+            r#"{ "version": 6, "bindings": {}, "repeat": { "Undo": { "interval": 0 } } }"#,
+        );
+        assert!(e.is_err());
+    }
+}
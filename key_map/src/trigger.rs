@@ -0,0 +1,55 @@
+/// When a binding runs its action.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// As soon as the keys are pressed.
+    #[default]
+    Press,
+    /// On release before the hold time has passed.
+    Tap,
+    /// Once the keys have been held for the hold time.
+    Hold,
+    /// Once the keys have been held for the hold time, and again on release.
+    WhileHeld,
+    /// On the second press within the double tap interval.
+    DoubleTap,
+}
+
+impl Trigger {
+    pub const ALL: [Self; 5] = [Self::Press, Self::Tap, Self::Hold, Self::WhileHeld, Self::DoubleTap];
+
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Press => "press",
+            Self::Tap => "tap",
+            Self::Hold => "hold",
+            Self::WhileHeld => "while_held",
+            Self::DoubleTap => "double_tap",
+        }
+    }
+
+    /// Returns `true` if the action waits for the release or the hold time.
+    #[inline]
+    pub fn is_gesture(&self) -> bool {
+        *self != Self::Press
+    }
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|t| t.as_str() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown trigger: {}", s))
+    }
+}
@@ -0,0 +1,225 @@
+use crate::*;
+use std::collections::BTreeMap;
+
+/// Shortcuts taken by MikuMikuEffect before MMAccel sees them.
+pub const MME_RESERVED: [&str; 1] = ["Ctrl+Shift+E"];
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Diagnostic {
+    /// The ID is not in mmd_map.json.
+    UnknownId { id: String },
+    /// The same sequence is bound to several actions and only one of them runs.
+    Duplicate { seq: KeySequence, ids: Vec<String> },
+    /// `prefix` runs `prefix_id` as soon as it is pressed, so `seq` can never be completed or held.
+    Shadowed {
+        id: String,
+        seq: KeySequence,
+        prefix: KeySequence,
+        prefix_id: String,
+    },
+    /// Only modifiers, which run whenever the modifier is used for another shortcut.
+    ModifierOnly { id: String, seq: KeySequence },
+    /// The first stroke is taken by MME.
+    Reserved { id: String, seq: KeySequence },
+}
+
+impl Diagnostic {
+    #[inline]
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::Duplicate { .. } | Self::Shadowed { .. } => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// Returns `true` if the diagnostic is about the binding of `id`.
+    pub fn concerns(&self, id: &str) -> bool {
+        match self {
+            Self::UnknownId { id: i } | Self::ModifierOnly { id: i, .. } | Self::Reserved { id: i, .. } => i == id,
+            Self::Duplicate { ids, .. } => ids.iter().any(|i| i == id),
+            Self::Shadowed { id: i, prefix_id, .. } => i == id || prefix_id == id,
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownId { id } => write!(f, "unknown id: {}", id),
+            Self::Duplicate { seq, ids } => write!(f, "{} is bound to {}", seq, ids.join(", ")),
+            Self::Shadowed {
+                id,
+                seq,
+                prefix,
+                prefix_id,
+            } => write!(f, "{} ({}) is shadowed by {} ({})", seq, id, prefix, prefix_id),
+            Self::ModifierOnly { id, seq } => write!(f, "{} ({}) has only modifiers", seq, id),
+            Self::Reserved { id, seq } => write!(f, "{} ({}) is reserved by MME", seq, id),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ValidationReport(Vec<Diagnostic>);
+
+impl ValidationReport {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<Diagnostic> {
+        self.0.iter()
+    }
+
+    #[inline]
+    pub fn has_errors(&self) -> bool {
+        self.0.iter().any(|d| d.severity() == Severity::Error)
+    }
+
+    /// The diagnostics about the binding of `id`.
+    pub fn of<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a Diagnostic> + 'a {
+        self.0.iter().filter(move |d| d.concerns(id))
+    }
+}
+
+impl std::iter::IntoIterator for ValidationReport {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    #[inline]
+    fn into_iter(self) -> std::vec::IntoIter<Diagnostic> {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> std::iter::IntoIterator for &'a ValidationReport {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    #[inline]
+    fn into_iter(self) -> std::slice::Iter<'a, Diagnostic> {
+        self.0.iter()
+    }
+}
+
+impl KeyMap {
+    /// Checks the bindings against the IDs in mmd_map.json. The diagnostics are sorted by ID.
+    pub fn validate<'a>(&self, known_ids: impl IntoIterator<Item = &'a str>) -> ValidationReport {
+        let known_ids = known_ids.into_iter().collect::<std::collections::HashSet<_>>();
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let mut owners: BTreeMap<String, (KeySequence, Vec<String>)> = BTreeMap::new();
+        for (id, bindings) in entries.iter() {
+            for seq in bindings.iter() {
+                let owner = owners.entry(seq.to_string()).or_insert_with(|| (seq.clone(), vec![]));
+                if !owner.1.contains(id) {
+                    owner.1.push(id.to_string());
+                }
+            }
+        }
+        let owner_of = |seq: &KeySequence| owners.get(&seq.to_string()).map(|(_, ids)| ids[0].clone());
+        let reserved = MME_RESERVED
+            .iter()
+            .filter_map(|s| s.parse::<Keys>().ok())
+            .collect::<Vec<_>>();
+        let mut v = vec![];
+        for (id, bindings) in entries.iter() {
+            if !known_ids.contains(id.as_str()) {
+                v.push(Diagnostic::UnknownId { id: id.to_string() });
+            }
+            for seq in bindings.iter() {
+                let first = match seq.strokes().first() {
+                    Some(first) => first,
+                    None => continue,
+                };
+                if seq.len() == 1 && first.is_modifiers_only() {
+                    v.push(Diagnostic::ModifierOnly {
+                        id: id.to_string(),
+                        seq: seq.clone(),
+                    });
+                }
+                if reserved.contains(&first.to_generic()) {
+                    v.push(Diagnostic::Reserved {
+                        id: id.to_string(),
+                        seq: seq.clone(),
+                    });
+                }
+                // A press of the same keys runs first, so a tap, hold or double tap never does.
+                let press = seq.trigger().is_gesture().then(|| seq.with_trigger(Trigger::Press));
+                let shadowing = seq.prefixes().chain(press).find_map(|p| owner_of(&p).map(|id| (p, id)));
+                if let Some((prefix, prefix_id)) = shadowing {
+                    v.push(Diagnostic::Shadowed {
+                        id: id.to_string(),
+                        seq: seq.clone(),
+                        prefix,
+                        prefix_id,
+                    });
+                }
+            }
+        }
+        for (id, _) in self.repeats().filter(|(id, _)| !known_ids.contains(id.as_str())) {
+            if self.get(id).is_none() {
+                v.push(Diagnostic::UnknownId { id: id.to_string() });
+            }
+        }
+        for (seq, ids) in owners.into_values() {
+            if ids.len() > 1 {
+                v.push(Diagnostic::Duplicate { seq, ids });
+            }
+        }
+        ValidationReport(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_test() {
+        let m: KeyMap = serde_json::from_str(
+            r#"{
+                "Undo": "Ctrl+Z",
+                "Redo": ["Ctrl+Z", "Ctrl+K"],
+                "Play": "Ctrl+K Ctrl+P",
+                "FramePrev": "Shift",
+                "FrameNext": "LCtrl+Shift+E",
+                "Hoge": "H",
+                "Stop": { "keys": "Ctrl+K", "trigger": "hold" }
+            }"#,
+        )
+        .unwrap();
+        let report = m.validate(
+            ["Undo", "Redo", "Play", "FramePrev", "FrameNext", "Stop"]
+                .iter()
+                .copied(),
+        );
+        let text = report.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        assert!(
+            text == [
+                "LCtrl+Shift+E (FrameNext) is reserved by MME",
+                "Shift (FramePrev) has only modifiers",
+                "unknown id: Hoge",
+                "Ctrl+K Ctrl+P (Play) is shadowed by Ctrl+K (Redo)",
+                "Ctrl+K (hold) (Stop) is shadowed by Ctrl+K (Redo)",
+                "Ctrl+Z is bound to Redo, Undo",
+            ]
+        );
+        assert!(report.has_errors());
+        assert!(report.of("Redo").count() == 3);
+        assert!(m.validate(["Redo"].iter().copied()).of("Hoge").count() == 1);
+    }
+}
@@ -1,14 +1,19 @@
 use crate::*;
-use handler::Handler;
-use key_map::KeyMap;
-use mmd_map::MmdMap;
+use handler::{get_window_text, Handler, Timing, GESTURE_TIMER_ID, MACRO_TIMER_ID, REPEAT_TIMER_ID};
+use key_map::{tr, Action, Catalog, KeyMap, Language, Overlay, Profiles};
+use macros::{Macro, MacroDefs};
+use midi::{MidiAction, MidiMap, MidiMapper, MidiMessage};
+use midi_input::{MidiInput, WM_MMACCEL_MIDI};
+use mmd_map::{Item, ItemKind, MenuTable, MmdMap, MmdVersion, Variant};
 use std::sync::{atomic, atomic::AtomicBool, Arc};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MenuItem {
     LaunchConfig,
+    Learn,
     RaiseTimerResolution(bool),
     KillFocusWithClick(bool),
+    Profile(usize),
     Version,
 }
 
@@ -16,12 +21,14 @@ impl MenuCommand for MenuItem {
     fn from_command(v: std::mem::Discriminant<Self>, item_type: MenuItemType) -> Self {
         match v {
             _ if v == std::mem::discriminant(&Self::LaunchConfig) => Self::LaunchConfig,
+            _ if v == std::mem::discriminant(&Self::Learn) => Self::Learn,
             _ if v == std::mem::discriminant(&Self::RaiseTimerResolution(false)) => {
                 Self::RaiseTimerResolution(item_type.as_with_check().unwrap())
             }
             _ if v == std::mem::discriminant(&Self::KillFocusWithClick(false)) => {
                 Self::KillFocusWithClick(item_type.as_with_check().unwrap())
             }
+            _ if v == std::mem::discriminant(&Self::Profile(0)) => Self::Profile(item_type.as_radio().unwrap()),
             _ if v == std::mem::discriminant(&Self::Version) => Self::Version,
             _ => unimplemented!(),
         }
@@ -34,32 +41,81 @@ struct MmdWindow {
     menu: Menu<MenuItem>,
 }
 
+fn build_menu(window: HWND, settings: &Settings, profiles: &[String], current: &str) -> Menu<MenuItem> {
+    let labels = profiles.iter().map(|name| Profiles::label(name)).collect::<Vec<_>>();
+    let checked = profiles.iter().position(|name| name == current).unwrap_or(0);
+    MenuBuilder::new(window, "MMAccel")
+        .item(&MenuItem::LaunchConfig, tr!("mmaccel.key_config"))
+        .item(&MenuItem::Learn, tr!("mmaccel.learn"))
+        .separator()
+        .radio_group(&MenuItem::Profile(0), &labels, checked)
+        .separator()
+        .with_check(
+            &MenuItem::RaiseTimerResolution(true),
+            tr!("mmaccel.raise_timer_resolution"),
+            settings.raise_timer_resolution,
+        )
+        .with_check(
+            &MenuItem::KillFocusWithClick(true),
+            tr!("mmaccel.kill_focus_with_click"),
+            settings.kill_focus_with_click,
+        )
+        .separator()
+        .item(&MenuItem::Version, tr!("mmaccel.version"))
+        .build()
+}
+
 impl MmdWindow {
     #[inline]
-    fn new(window: HWND, settings: &Settings) -> Self {
+    fn new(window: HWND, settings: &Settings, profiles: &[String], current: &str) -> Self {
         Self {
             window,
             sub_window: None,
-            menu: MenuBuilder::new(window, "MMAccel")
-                .item(&MenuItem::LaunchConfig, "キー設定")
-                .separator()
-                .with_check(
-                    &MenuItem::RaiseTimerResolution(true),
-                    "タイマーの精度を上げる",
-                    settings.raise_timer_resolution,
-                )
-                .with_check(
-                    &MenuItem::KillFocusWithClick(true),
-                    "クリックで入力状態を解除",
-                    settings.kill_focus_with_click,
-                )
-                .separator()
-                .item(&MenuItem::Version, "バージョン情報")
-                .build(),
+            menu: build_menu(window, settings, profiles, current),
         }
     }
 }
 
+impl MmdWindow {
+    #[inline]
+    fn contains(&self, hwnd: HWND) -> bool {
+        unsafe {
+            hwnd == self.window
+                || GetParent(hwnd) == self.window
+                || Some(hwnd) == self.sub_window
+                || self.sub_window.map_or(false, |sw| GetParent(hwnd) == sw)
+        }
+    }
+
+    /// The control of MMD that `hwnd` belongs to, where the edit in a combo box belongs to the combo box.
+    fn learn_target(&self, hwnd: HWND) -> Option<HWND> {
+        unsafe {
+            let parent = GetParent(hwnd);
+            let hwnd = if get_class_name(parent).to_ascii_uppercase() == "COMBOBOX" {
+                parent
+            } else {
+                hwnd
+            };
+            (self.contains(hwnd) && hwnd != self.window && Some(hwnd) != self.sub_window).then(|| hwnd)
+        }
+    }
+}
+
+/// `WM_KEY_CONFIG_RELOAD` of key_config, which reads the current profile again.
+const WM_KEY_CONFIG_RELOAD: u32 = WM_APP + 12;
+
+/// Returns the key code of a bindable mouse input.
+fn mouse_vk(data: &MSG) -> u32 {
+    let hiword = (data.wParam.0 >> 16) as u16;
+    match data.message {
+        WM_MBUTTONDOWN | WM_MBUTTONUP => VK_MBUTTON.0 as _,
+        WM_XBUTTONDOWN | WM_XBUTTONUP if hiword == XBUTTON1.0 as u16 => VK_XBUTTON1.0 as _,
+        WM_XBUTTONDOWN | WM_XBUTTONUP => VK_XBUTTON2.0 as _,
+        _ if hiword as i16 > 0 => key_map::WHEEL_UP,
+        _ => key_map::WHEEL_DOWN,
+    }
+}
+
 struct TimePeriod(u32);
 
 impl TimePeriod {
@@ -89,6 +145,11 @@ fn version_info(hwnd: HWND) {
 struct Settings {
     raise_timer_resolution: bool,
     kill_focus_with_click: bool,
+    sequence_timeout: u64,
+    hold_time: u64,
+    double_tap_interval: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
 }
 
 impl Settings {
@@ -112,12 +173,36 @@ impl Settings {
                         .get("kill_focus_with_click")
                         .and_then(|v| v.as_bool())
                         .unwrap_or(default.kill_focus_with_click),
+                    sequence_timeout: obj
+                        .get("sequence_timeout")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(default.sequence_timeout),
+                    hold_time: obj
+                        .get("hold_time")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(default.hold_time),
+                    double_tap_interval: obj
+                        .get("double_tap_interval")
+                        .and_then(|v| v.as_u64())
+                        .unwrap_or(default.double_tap_interval),
+                    language: obj
+                        .get("language")
+                        .and_then(|v| v.as_str())
+                        .and_then(|s| s.parse().ok()),
                 })
             }
             Err(_) => None,
         }
     }
 
+    fn timing(&self) -> Timing {
+        Timing {
+            sequence_timeout: std::time::Duration::from_millis(self.sequence_timeout),
+            hold_time: std::time::Duration::from_millis(self.hold_time),
+            double_tap_interval: std::time::Duration::from_millis(self.double_tap_interval),
+        }
+    }
+
     fn to_file(&self, module_path: &std::path::Path) {
         if let Ok(file) = std::fs::File::create(module_path.join(Self::PATH)) {
             serde_json::to_writer_pretty(std::io::BufWriter::new(file), self).ok();
@@ -130,17 +215,178 @@ impl Default for Settings {
         Self {
             raise_timer_resolution: true,
             kill_focus_with_click: true,
+            sequence_timeout: 1000,
+            hold_time: 300,
+            double_tap_interval: 250,
+            language: None,
+        }
+    }
+}
+
+const MMD_MAP_DIR: &str = "MMAccel";
+const OVERLAY_DIR: &str = "MMAccel/mmd_map.d";
+const PROFILES_DIR: &str = "MMAccel";
+const MIDI_MAP_PATH: &str = "MMAccel/midi_map.json";
+const MACROS_PATH: &str = "MMAccel/macros.json";
+const LOCALE_DIR: &str = "MMAccel/locale";
+
+/// Writes the default key map only when there is no file, so that a file this version cannot read is kept.
+fn load_key_map(path: &std::path::Path) -> KeyMap {
+    match KeyMap::load(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let m = KeyMap::default();
+            if m.to_file(path).is_ok() {
+                log::debug!("written {}", path.display());
+            }
+            m
+        }
+        Err(e) => {
+            log::error!("{}: {}", path.display(), e);
+            KeyMap::default()
         }
     }
 }
 
-const MMD_MAP_PATH: &str = "MMAccel/mmd_map.json";
-const KEY_MAP_PATH: &str = "MMAccel/key_map.json";
+/// The file version in the version resource of MikuMikuDance.exe.
+fn mmd_version() -> Option<MmdVersion> {
+    use windows::Win32::Storage::FileSystem::*;
+
+    let path = to_wchar(get_module_path().to_string_lossy());
+    unsafe {
+        let size = GetFileVersionInfoSizeW(PWSTR(path.as_ptr() as _), std::ptr::null_mut());
+        if size == 0 {
+            return None;
+        }
+        let mut data = vec![0u8; size as usize];
+        if !GetFileVersionInfoW(PWSTR(path.as_ptr() as _), 0, size, data.as_mut_ptr() as _).as_bool() {
+            return None;
+        }
+        let mut info: *mut VS_FIXEDFILEINFO = std::ptr::null_mut();
+        let mut len = 0;
+        let root = to_wchar("\\");
+        if !VerQueryValueW(
+            data.as_ptr() as _,
+            PWSTR(root.as_ptr() as _),
+            &mut info as *mut _ as _,
+            &mut len,
+        )
+        .as_bool()
+            || info.is_null()
+        {
+            return None;
+        }
+        let info = &*info;
+        Some(MmdVersion::from_ms_ls(info.dwFileVersionMS, info.dwFileVersionLS))
+    }
+}
+
+/// Loads the mmd_map for the version and the language mode of MMD, with the user overlays in `overlay_dir`.
+/// Also returns the file name of the selected mmd_map, which key_config reads too.
+fn load_mmd_map(dir: &std::path::Path, overlay_dir: &std::path::Path) -> std::io::Result<(MmdMap, String)> {
+    let variant = Variant {
+        version: mmd_version(),
+        english: unsafe { mmd::ExpGetEnglishMode() },
+    };
+    let selected = variant.select(dir);
+    let file_name = selected
+        .path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    log::info!("{:?}: {}", variant, file_name);
+    if !selected.is_known {
+        let version = variant.version.map_or("unknown".to_string(), |v| v.to_string());
+        log::warn!(
+            "MMD version {} is not supported, {} may not match the controls and the menus",
+            version,
+            file_name
+        );
+    }
+    let (overlays, overlay_errors) = Overlay::load_dir(overlay_dir);
+    let (mmd_map, errors, conflicts) = MmdMap::load_with_overlays(&selected.path, &overlays)?;
+    for e in errors.iter() {
+        log::error!("{}: {}", file_name, e);
+    }
+    for e in overlay_errors.iter() {
+        log::error!("mmd_map.d: {}", e);
+    }
+    for e in conflicts.iter() {
+        log::warn!("mmd_map.d: {}", e);
+    }
+    Ok((mmd_map, file_name))
+}
+
+/// Sets the catalog of the language in settings.json, or of the language mode of MMD.
+fn load_catalog(dir: &std::path::Path, language: Option<Language>) -> Language {
+    let language = language.unwrap_or_else(|| {
+        if unsafe { mmd::ExpGetEnglishMode() } {
+            Language::En
+        } else {
+            Language::Ja
+        }
+    });
+    log::debug!("language: {}", language);
+    key_map::set_catalog(Catalog::load(dir, language).unwrap_or_else(|e| {
+        log::error!("{}.json: {}", language, e);
+        Catalog::builtin(language)
+    }));
+    language
+}
+
+/// Adds the macros to `mmd_map` so that they are bound like the other items.
+fn load_macros(path: &std::path::Path, mmd_map: &mut MmdMap) -> Vec<Macro> {
+    let defs = match MacroDefs::from_file(path) {
+        Ok(defs) => defs,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            log::error!("macros.json: {}", e);
+            return vec![];
+        }
+    };
+    let (macros, errors) = defs.resolve(mmd_map);
+    for e in errors.iter() {
+        log::error!("macros.json: {}", e);
+    }
+    for (i, m) in macros.iter().enumerate() {
+        mmd_map.push(
+            &m.id,
+            Item {
+                name: m.name.clone(),
+                kind: ItemKind::Macro(i),
+            },
+        );
+    }
+    macros
+}
+
+fn load_midi_map(path: &std::path::Path, mmd_map: &MmdMap) -> Option<MidiMapper> {
+    let midi_map = match MidiMap::from_file(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            log::error!("midi_map.json: {}", e);
+            return None;
+        }
+    };
+    for id in midi_map.action_ids().filter(|id| mmd_map.get(id).is_none()) {
+        log::error!("midi_map.json: unknown id {}", id);
+    }
+    Some(MidiMapper::new(midi_map))
+}
 
 pub struct Context {
     module_path: std::path::PathBuf,
     settings: Settings,
+    language: Language,
     mmd_map: MmdMap,
+    mmd_map_file: String,
+    menu_table: MenuTable,
+    macros: Vec<Macro>,
+    profiles: Profiles,
+    profile_names: Vec<String>,
+    current_profile: String,
     _call_window_proc_ret: HookHandle,
     _get_message_handle: HookHandle,
     mmd_window: Option<MmdWindow>,
@@ -148,7 +394,11 @@ pub struct Context {
     file_monitor: FileMonitor,
     latest_key_map: Arc<AtomicBool>,
     key_config: Option<HWND>,
+    /// The next click on a control or a menu item of MMD is recorded as an action.
+    learning: bool,
     time_period: Option<TimePeriod>,
+    midi_mapper: Option<MidiMapper>,
+    midi_input: Option<MidiInput>,
 }
 
 impl Context {
@@ -156,22 +406,29 @@ impl Context {
     pub fn new(module_path: std::path::PathBuf) -> std::io::Result<Self> {
         let settings = Settings::from_file(&module_path).unwrap_or_default();
         log::debug!("{:?}", settings);
-        let mmd_map = MmdMap::from_file(module_path.join(MMD_MAP_PATH))?;
-        let key_map = KeyMap::from_file(module_path.join(KEY_MAP_PATH)).unwrap_or_else(|_| {
-            let m = KeyMap::default();
-            if let Ok(file) = std::fs::File::create(module_path.join(KEY_MAP_PATH)) {
-                serde_json::to_writer_pretty(std::io::BufWriter::new(file), &m).ok();
-                log::debug!("written key_map.json");
-            }
-            m
-        });
-        let handler = Handler::new(&mmd_map, key_map);
+        let language = load_catalog(&module_path.join(LOCALE_DIR), settings.language);
+        let (mut mmd_map, mmd_map_file) = load_mmd_map(&module_path.join(MMD_MAP_DIR), &module_path.join(OVERLAY_DIR))?;
+        let macros = load_macros(&module_path.join(MACROS_PATH), &mut mmd_map);
+        let profiles = Profiles::new(module_path.join(PROFILES_DIR));
+        let profile_names = profiles.names();
+        let current_profile = profiles.current();
+        log::debug!("profile: {}", current_profile);
+        let key_map = load_key_map(&profiles.path(&current_profile));
+        let handler = Handler::new(&mmd_map, &macros, key_map, settings.timing());
+        let midi_mapper = load_midi_map(&module_path.join(MIDI_MAP_PATH), &mmd_map);
         let file_monitor = FileMonitor::new();
         let time_period = settings.raise_timer_resolution.then(|| TimePeriod::new(1));
         Ok(Self {
             module_path,
             settings,
+            language,
             mmd_map,
+            mmd_map_file,
+            menu_table: MenuTable::default(),
+            macros,
+            profiles,
+            profile_names,
+            current_profile,
             _call_window_proc_ret: HookHandle::new(
                 WH_CALLWNDPROCRET,
                 Some(hook_call_window_proc_ret),
@@ -183,7 +440,10 @@ impl Context {
             file_monitor,
             latest_key_map: Arc::new(AtomicBool::new(true)),
             key_config: None,
+            learning: false,
             time_period,
+            midi_mapper,
+            midi_input: None,
         })
     }
 
@@ -191,14 +451,31 @@ impl Context {
         match data.message {
             WM_CREATE if get_class_name(data.hwnd) == "Polygon Movie Maker" => {
                 log::debug!("created MainWindow");
-                self.mmd_window = Some(MmdWindow::new(data.hwnd, &self.settings));
+                // Read before MMAccel and the other plugins insert their menus.
+                self.menu_table = menu::read_menu_table(data.hwnd);
+                let not_found = self.mmd_map.resolve_menus(&self.menu_table);
+                if !not_found.is_empty() {
+                    log::warn!("menu items not found: {}", not_found.join(", "));
+                }
+                self.load_handler();
+                self.mmd_window = Some(MmdWindow::new(
+                    data.hwnd,
+                    &self.settings,
+                    &self.profile_names,
+                    &self.current_profile,
+                ));
+                if let Some(mapper) = self.midi_mapper.as_ref() {
+                    self.midi_input = MidiInput::open(mapper.midi_map().device.as_deref(), data.hwnd);
+                }
                 let latest_key_map = self.latest_key_map.clone();
                 let mmd_window = self.mmd_window.as_ref().unwrap().window;
                 self.file_monitor.start("MMAccel", move |path| unsafe {
-                    if path.file_name() == Some(std::ffi::OsStr::new("key_map.json")) {
-                        latest_key_map.store(false, atomic::Ordering::SeqCst);
-                        PostMessageW(mmd_window, WM_APP, WPARAM(0), LPARAM(0));
-                        log::debug!("update key_map.json");
+                    if let Some(file_name) = path.file_name().and_then(|f| f.to_str()) {
+                        if Profiles::is_profile_file(file_name) {
+                            latest_key_map.store(false, atomic::Ordering::SeqCst);
+                            PostMessageW(mmd_window, WM_APP, WPARAM(0), LPARAM(0));
+                            log::debug!("update {}", file_name);
+                        }
                     }
                 });
             }
@@ -216,6 +493,7 @@ impl Context {
                         }
                     }
                 }
+                self.midi_input = None;
                 if let Some(jh) = self.file_monitor.stop() {
                     jh.join().ok();
                     log::debug!("stop FileMonitor");
@@ -236,9 +514,95 @@ impl Context {
         }
     }
 
+    fn load_handler(&mut self) {
+        let key_map = load_key_map(&self.profiles.path(&self.current_profile));
+        self.handler = Handler::new(&self.mmd_map, &self.macros, key_map, self.settings.timing());
+    }
+
+    /// Reads the profiles again and rebuilds `Handler` from the selected one.
+    fn reload_profiles(&mut self) {
+        let profile_names = self.profiles.names();
+        let current_profile = self.profiles.current();
+        if profile_names != self.profile_names || current_profile != self.current_profile {
+            self.profile_names = profile_names;
+            self.current_profile = current_profile;
+            log::debug!("profile: {}", self.current_profile);
+            if let Some(mmd_window) = self.mmd_window.as_mut() {
+                mmd_window.menu = build_menu(
+                    mmd_window.window,
+                    &self.settings,
+                    &self.profile_names,
+                    &self.current_profile,
+                );
+            }
+        }
+        self.load_handler();
+    }
+
+    fn select_profile(&mut self, index: usize) {
+        let name = match self.profile_names.get(index) {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        if let Err(e) = self.profiles.set_current(&name) {
+            log::error!("profile.json: {}", e);
+        }
+        self.current_profile = name;
+        log::debug!("profile: {}", self.current_profile);
+        self.load_handler();
+    }
+
+    /// A menu item of MMD chosen in learn mode, which is not one of MMAccel or the other plugins.
+    fn learned_menu_item(&self, data: &MSG) -> Option<Action> {
+        if !self.learning || data.wParam.0 >> 16 != 0 || data.lParam.0 != 0 {
+            return None;
+        }
+        let command = (data.wParam.0 & 0xffff) as u32;
+        self.menu_table
+            .0
+            .iter()
+            .find(|entry| entry.command == command)
+            .map(|entry| Action::menu_item(command, &entry.path))
+    }
+
+    /// Adds `action` to the current profile and lets key_config read it.
+    fn learn(&mut self, action: Action) {
+        self.learning = false;
+        let path = self.profiles.path(&self.current_profile);
+        // Unlike `load_key_map`, a file that cannot be read is not replaced with the default bindings.
+        let mut key_map = match KeyMap::load(&path) {
+            Ok(m) => m,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => KeyMap::default(),
+            Err(e) => {
+                log::error!("learn canceled: {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let id = key_map.new_action_id(|id| self.mmd_map.get(id).is_some());
+        let name = action.name.clone();
+        log::debug!("learn: {} {:?}", id, action);
+        key_map.insert_action(&id, action);
+        if let Err(e) = key_map.to_file(&path) {
+            log::error!("{}: {}", path.display(), e);
+            return;
+        }
+        unsafe {
+            if let Some(kc) = self.key_config.filter(|&kc| IsWindow(kc).as_bool()) {
+                PostMessageW(kc, WM_KEY_CONFIG_RELOAD, WPARAM(0), LPARAM(0));
+            }
+        }
+        let window = self.mmd_window.as_ref().map(|mw| mw.window);
+        message_box(window, tr!("mmaccel.learned", name, id), "MMAccel", MB_OK);
+    }
+
     pub fn get_message(&mut self, data: &mut MSG) -> bool {
         match data.message {
             WM_COMMAND => {
+                if let Some(action) = self.learned_menu_item(data) {
+                    self.learn(action);
+                    return true;
+                }
+                let mut selected_profile = None;
                 if let Some(mmd_window) = self.mmd_window.as_ref() {
                     match mmd_window.menu.recv_command(data.wParam) {
                         Some(MenuItem::LaunchConfig) => {
@@ -246,6 +610,10 @@ impl Context {
                             let key_config_process = std::process::Command::new(&path)
                                 .current_dir(self.module_path.join("MMAccel"))
                                 .arg("--mmd")
+                                .arg("--language")
+                                .arg(self.language.as_str())
+                                .arg("--mmd-map")
+                                .arg(&self.mmd_map_file)
                                 .stdout(std::process::Stdio::piped())
                                 .spawn();
                             match key_config_process {
@@ -270,44 +638,88 @@ impl Context {
                                 Err(e) => log::error!("LaunchCconfig: {:?}", e),
                             }
                         }
+                        Some(MenuItem::Learn) => {
+                            message_box(Some(mmd_window.window), tr!("mmaccel.learn_start"), "MMAccel", MB_OK);
+                            self.learning = true;
+                            log::debug!("learn: start");
+                        }
                         Some(MenuItem::RaiseTimerResolution(b)) => {
                             self.time_period = if b { Some(TimePeriod::new(1)) } else { None };
+                            self.settings.raise_timer_resolution = b;
                         }
                         Some(MenuItem::KillFocusWithClick(b)) => {
                             self.settings.kill_focus_with_click = b;
                         }
+                        Some(MenuItem::Profile(index)) => selected_profile = Some(index),
                         Some(MenuItem::Version) => version_info(mmd_window.window),
                         _ => {}
                     }
                 }
+                if let Some(index) = selected_profile {
+                    self.select_profile(index);
+                }
+            }
+            WM_KEYDOWN if self.learning && data.wParam.0 == VK_ESCAPE.0 as usize => {
+                self.learning = false;
+                log::debug!("learn: cancel");
+                return true;
             }
-            WM_KEYDOWN | WM_SYSKEYDOWN => unsafe {
+            WM_KEYDOWN | WM_SYSKEYDOWN => {
                 let mmd_window = self.mmd_window.as_ref().unwrap();
-                let main_window = mmd_window.window;
-                let sub_window = mmd_window.sub_window;
-                let cond = data.hwnd == main_window
-                    || GetParent(data.hwnd) == main_window
-                    || Some(data.hwnd) == sub_window
-                    || sub_window.map_or(false, |sw| GetParent(data.hwnd) == sw);
-                if cond {
-                    self.handler
-                        .key_down(data.wParam.0 as u32, main_window, sub_window, data.hwnd);
+                if mmd_window.contains(data.hwnd) {
+                    self.handler.key_down(
+                        data.wParam.0 as u32,
+                        (data.lParam.0 >> 30) & 1 != 0,
+                        mmd_window.window,
+                        mmd_window.sub_window,
+                        data.hwnd,
+                    );
                     return true;
                 }
-            },
-            WM_KEYUP | WM_SYSKEYUP => unsafe {
+            }
+            WM_KEYUP | WM_SYSKEYUP => {
                 let mmd_window = self.mmd_window.as_ref().unwrap();
-                let main_window = mmd_window.window;
-                let sub_window = mmd_window.sub_window;
-                let cond = data.hwnd == main_window
-                    || GetParent(data.hwnd) == main_window
-                    || Some(data.hwnd) == sub_window
-                    || sub_window.map_or(false, |sw| GetParent(data.hwnd) == sw);
-                if cond {
-                    self.handler.key_up(data.wParam.0 as u32);
+                if mmd_window.contains(data.hwnd) {
+                    self.handler.key_up(data.wParam.0 as u32, mmd_window.window);
                     return true;
                 }
-            },
+            }
+            // Unbound mouse input is left to MMD, e.g. dragging with the middle button.
+            WM_MBUTTONDOWN | WM_XBUTTONDOWN | WM_MOUSEWHEEL => {
+                if let Some(mmd_window) = self.mmd_window.as_ref() {
+                    if mmd_window.contains(data.hwnd) {
+                        return self.handler.key_down(
+                            mouse_vk(data),
+                            false,
+                            mmd_window.window,
+                            mmd_window.sub_window,
+                            data.hwnd,
+                        );
+                    }
+                }
+            }
+            WM_MBUTTONUP | WM_XBUTTONUP => {
+                if let Some(mmd_window) = self.mmd_window.as_ref() {
+                    if mmd_window.contains(data.hwnd) {
+                        self.handler.key_up(mouse_vk(data), mmd_window.window);
+                    }
+                }
+            }
+            WM_LBUTTONDOWN if self.learning => {
+                let target = self.mmd_window.as_ref().and_then(|mw| mw.learn_target(data.hwnd));
+                if let Some(hwnd) = target {
+                    let action = unsafe {
+                        Action::control(
+                            GetDlgCtrlID(hwnd) as u32,
+                            &get_class_name(hwnd),
+                            &get_class_name(GetParent(hwnd)),
+                            &get_window_text(hwnd),
+                        )
+                    };
+                    self.learn(action);
+                    return true;
+                }
+            }
             WM_LBUTTONDOWN => unsafe {
                 if self.settings.kill_focus_with_click {
                     let main_window = self.mmd_window.as_ref().unwrap().window;
@@ -318,17 +730,28 @@ impl Context {
                     }
                 }
             },
+            WM_TIMER if data.wParam.0 == GESTURE_TIMER_ID => {
+                if let Some(mmd_window) = self.mmd_window.as_ref() {
+                    self.handler.timer(mmd_window.window);
+                    return true;
+                }
+            }
+            WM_TIMER if data.wParam.0 == REPEAT_TIMER_ID => {
+                if let Some(mmd_window) = self.mmd_window.as_ref() {
+                    self.handler.repeat_timer(mmd_window.window);
+                    return true;
+                }
+            }
+            WM_TIMER if data.wParam.0 == MACRO_TIMER_ID => {
+                if let Some(mmd_window) = self.mmd_window.as_ref() {
+                    self.handler.macro_timer(mmd_window.window);
+                    return true;
+                }
+            }
+            WM_MMACCEL_MIDI => self.midi_message(data.wParam.0 as u32),
             WM_APP => {
                 if !self.latest_key_map.swap(true, atomic::Ordering::SeqCst) {
-                    let key_map = KeyMap::from_file(self.module_path.join(KEY_MAP_PATH)).unwrap_or_else(|_| {
-                        let m = KeyMap::default();
-                        if let Ok(file) = std::fs::File::create(self.module_path.join(KEY_MAP_PATH)) {
-                            serde_json::to_writer_pretty(std::io::BufWriter::new(file), &m).ok();
-                            log::debug!("written key_map.json");
-                        }
-                        m
-                    });
-                    self.handler = Handler::new(&self.mmd_map, key_map);
+                    self.reload_profiles();
                 }
             }
             _ => {}
@@ -336,6 +759,34 @@ impl Context {
         false
     }
 
+    fn midi_message(&mut self, param: u32) {
+        let (mmd_window, mapper) = match (self.mmd_window.as_ref(), self.midi_mapper.as_mut()) {
+            (Some(mmd_window), Some(mapper)) => (mmd_window, mapper),
+            _ => return,
+        };
+        let msg = match MidiMessage::from_short_message(param) {
+            Some(msg) => msg,
+            None => return,
+        };
+        log::debug!("MIDI: {:?}", msg);
+        let focus = unsafe { GetFocus() };
+        for action in mapper.map(&msg) {
+            let item = match self.mmd_map.get(action.id()) {
+                Some(item) => item.kind,
+                None => continue,
+            };
+            match action {
+                MidiAction::Press(_) => self
+                    .handler
+                    .press(item, mmd_window.window, mmd_window.sub_window, focus),
+                MidiAction::Release(_) => self.handler.release(item),
+                MidiAction::Trigger(_) => self
+                    .handler
+                    .trigger(item, mmd_window.window, mmd_window.sub_window, focus),
+            }
+        }
+    }
+
     pub fn get_key_state(&self, vk: u32) -> Option<u16> {
         if vk >= 0x07 {
             if self.handler.is_pressed(vk) {
@@ -40,7 +40,7 @@ impl FileMonitor {
                     buffer.as_mut_ptr() as _,
                     buffer.len() as _,
                     false,
-                    FILE_NOTIFY_CHANGE_LAST_WRITE,
+                    FILE_NOTIFY_CHANGE_FILE_NAME | FILE_NOTIFY_CHANGE_LAST_WRITE,
                     &mut len,
                     std::ptr::null_mut(),
                     None,
@@ -1,20 +1,546 @@
+use crate::macros::*;
 use crate::mmd_map::*;
 use crate::*;
 use key_map::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// How long a key item stays pressed when its input has no key up, such as the wheel.
+/// Shorter than `KEY_STEP_INTERVAL` so that the key steps of a macro are released in between.
+const MOMENTARY_PRESS_DURATION: Duration = Duration::from_millis(50);
+
+/// The timer on the MMD window that ends a hold or a pending tap.
+pub const GESTURE_TIMER_ID: usize = 0x4d4d41;
+/// The timer on the MMD window for `Repeat::Custom`.
+pub const REPEAT_TIMER_ID: usize = 0x4d4d42;
+/// The timer on the MMD window for the delays and the waits of a macro.
+pub const MACRO_TIMER_ID: usize = 0x4d4d43;
+
+/// How often a macro looks at the control it waits for.
+const MACRO_POLL_INTERVAL: u32 = 15;
+
+/// Thresholds in settings.json.
+#[derive(Clone, Copy, Debug)]
+pub struct Timing {
+    pub sequence_timeout: Duration,
+    pub hold_time: Duration,
+    pub double_tap_interval: Duration,
+}
+
+#[inline]
+fn is_edit(hwnd: HWND) -> bool {
+    get_class_name(hwnd).to_ascii_uppercase() == "EDIT"
+}
+
+/// The control in the main window, or in the sub window.
+#[inline]
+fn get_control(mmd_window: HWND, sub_window: Option<HWND>, id: u32) -> HWND {
+    unsafe {
+        let hwnd = GetDlgItem(mmd_window, id as _);
+        hwnd.is_invalid()
+            .then(|| sub_window.map(|sw| GetDlgItem(sw, id as _)))
+            .flatten()
+            .unwrap_or(hwnd)
+    }
+}
+
+#[inline]
+unsafe fn post_set_cur_sel(hwnd: HWND, id: u32, parent: HWND, index: isize) {
+    PostMessageW(hwnd, CB_SETCURSEL, WPARAM(index as _), LPARAM(0));
+    PostMessageW(
+        parent,
+        WM_COMMAND,
+        WPARAM(((id & 0xffff) | (CBN_SELCHANGE << 16)) as _),
+        LPARAM(hwnd.0),
+    );
+    log::debug!("Combo: 0x{:x}", id);
+}
+
+/// The text of the combo box entry, which is in Shift-JIS.
+fn get_combo_text(hwnd: HWND, index: isize) -> Option<String> {
+    unsafe {
+        let len = SendMessageA(hwnd, CB_GETLBTEXTLEN, WPARAM(index as _), LPARAM(0)).0;
+        if len < 0 {
+            return None;
+        }
+        let mut buffer = vec![0u8; len as usize + 1];
+        let len = SendMessageA(hwnd, CB_GETLBTEXT, WPARAM(index as _), LPARAM(buffer.as_mut_ptr() as _)).0;
+        (len >= 0).then(|| from_sjis(&buffer[..len as usize]))
+    }
+}
+
+/// The command ID of a menu item. `menu_commands` is read when the menu of MMD is built.
+fn menu_command(item: &ItemKind, mmd_window: HWND, menu_commands: &HashMap<ItemKind, u32>) -> Option<u32> {
+    match item {
+        ItemKind::MenuCommand(command) => Some(*command),
+        ItemKind::Menu(index, sub_index) => menu_commands.get(item).copied().or_else(|| unsafe {
+            let m = GetSubMenu(GetMenu(mmd_window), *index as _);
+            Some(GetMenuItemID(m, *sub_index as _)).filter(|&command| command != u32::MAX)
+        }),
+        ItemKind::MenuPath(_) => menu_commands.get(item).copied(),
+        _ => None,
+    }
+}
+
+/// Whether the button or the menu item is checked.
+fn is_checked(
+    item: &ItemKind,
+    mmd_window: HWND,
+    sub_window: Option<HWND>,
+    menu_commands: &HashMap<ItemKind, u32>,
+) -> bool {
+    // BST_CHECKED
+    const CHECKED: isize = 1;
+    unsafe {
+        match item {
+            ItemKind::Button(id) => {
+                let hwnd = get_control(mmd_window, sub_window, *id);
+                SendMessageW(hwnd, BM_GETCHECK, WPARAM(0), LPARAM(0)).0 == CHECKED
+            }
+            ItemKind::Menu(..) | ItemKind::MenuCommand(_) | ItemKind::MenuPath(_) => {
+                match menu_command(item, mmd_window, menu_commands) {
+                    Some(command) => {
+                        let state = GetMenuState(GetMenu(mmd_window), command, MF_BYCOMMAND);
+                        state != u32::MAX && (state & MFS_CHECKED.0) != 0
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn get_window_text(hwnd: HWND) -> String {
+    unsafe {
+        let mut buffer = vec![0; 64];
+        let size = GetWindowTextW(hwnd, PWSTR(buffer.as_mut_ptr()), buffer.len() as _);
+        String::from_utf16_lossy(&buffer[..size.max(0) as usize])
+    }
+}
+
+/// Items bound in `KeyContext::Edit` also run while a text box has the focus.
+#[allow(clippy::too_many_arguments)]
+fn handle(
+    item: &ItemKind,
+    context: Option<KeyContext>,
+    key_states: &mut HashMap<u32, bool>,
+    folds: &[u32],
+    unfolds: &[u32],
+    menu_commands: &HashMap<ItemKind, u32>,
+    mmd_window: HWND,
+    sub_window: Option<HWND>,
+    hwnd: HWND,
+) {
+    if context != Some(KeyContext::Edit) && is_edit(hwnd) {
+        unsafe {
+            if item == &ItemKind::KillFocus {
+                SetFocus(mmd_window);
+                log::debug!("KillFocus")
+            }
+        }
+        return;
+    }
+    match item {
+        ItemKind::Key(k) => {
+            if key_states.get(k).is_some() && *k != VK_SHIFT.0 as u32 && *k != VK_CONTROL.0 as u32 {
+                *key_states.get_mut(&(VK_SHIFT.0 as u32)).unwrap() = false;
+                *key_states.get_mut(&(VK_CONTROL.0 as u32)).unwrap() = false;
+            }
+            if let Some(ks) = key_states.get_mut(k) {
+                *ks = true;
+                log::debug!("Key: 0x{:x}", k);
+            }
+        }
+        ItemKind::Button(id) => unsafe {
+            let hwnd = get_control(mmd_window, sub_window, *id);
+            if IsWindowVisible(hwnd).as_bool() && IsWindowEnabled(hwnd).as_bool() {
+                PostMessageA(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
+                log::debug!("Button: 0x{:x}", id);
+            }
+        },
+        ItemKind::Edit(id) => unsafe {
+            let hwnd = get_control(mmd_window, sub_window, *id);
+            if IsWindowVisible(hwnd).as_bool() && IsWindowEnabled(hwnd).as_bool() {
+                SetFocus(hwnd);
+                log::debug!("Edit: 0x{:x}", id);
+            }
+        },
+        ItemKind::EditValue(id, op) => unsafe {
+            let hwnd = get_control(mmd_window, sub_window, *id);
+            if !IsWindowVisible(hwnd).as_bool() || !IsWindowEnabled(hwnd).as_bool() {
+                return;
+            }
+            let text = match op.apply(&get_window_text(hwnd)) {
+                Some(text) => text,
+                None => return,
+            };
+            // MMD reads the value when Enter is pressed in the control.
+            SetFocus(hwnd);
+            let wtext = to_wchar(&text);
+            SetWindowTextW(hwnd, PWSTR(wtext.as_ptr() as _));
+            SendMessageW(hwnd, WM_KEYDOWN, WPARAM(VK_RETURN.0 as _), LPARAM(0));
+            SendMessageW(hwnd, WM_KEYUP, WPARAM(VK_RETURN.0 as _), LPARAM(0));
+            SetFocus(mmd_window);
+            log::debug!("EditValue: 0x{:x} {}", id, text);
+        },
+        ItemKind::Combo(dir, id) => unsafe {
+            let hwnd = get_control(mmd_window, sub_window, *id);
+            if !IsWindowVisible(hwnd).as_bool() || !IsWindowEnabled(hwnd).as_bool() {
+                return;
+            }
+            let index = SendMessageA(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
+            let size = SendMessageA(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0;
+            match dir {
+                ComboDir::Prev if index >= 1 => post_set_cur_sel(hwnd, *id, mmd_window, index - 1),
+                ComboDir::Next if index < size - 1 => post_set_cur_sel(hwnd, *id, mmd_window, index + 1),
+                _ => {}
+            }
+        },
+        ItemKind::Menu(..) | ItemKind::MenuCommand(_) | ItemKind::MenuPath(_) => unsafe {
+            let command = match menu_command(item, mmd_window, menu_commands) {
+                Some(command) => command,
+                None => return,
+            };
+            let state = GetMenuState(GetMenu(mmd_window), command, MF_BYCOMMAND);
+            if state != u32::MAX && (state & MFS_DISABLED.0) == 0 {
+                PostMessageA(mmd_window, WM_COMMAND, WPARAM(command as _), LPARAM(0));
+                log::debug!("Menu: {:?} {}", item, command);
+            }
+        },
+        ItemKind::Fold(hide_id, show_id) => unsafe {
+            let hide = GetDlgItem(mmd_window, *hide_id as _);
+            if IsWindowVisible(hide).as_bool() {
+                PostMessageW(hide, BM_CLICK, WPARAM(0), LPARAM(0));
+                log::debug!("Fold: 0x{:x}", hide_id);
+            } else {
+                let show = GetDlgItem(mmd_window, *show_id as _);
+                PostMessageW(show, BM_CLICK, WPARAM(0), LPARAM(0));
+                log::debug!("Fold: 0x{:x}", show_id);
+            }
+        },
+        ItemKind::KillFocus => unsafe {
+            SetFocus(mmd_window);
+            log::debug!("KillFocus");
+        },
+        ItemKind::FoldAll => unsafe {
+            for id in folds {
+                let hwnd = GetDlgItem(mmd_window, *id as _);
+                if IsWindowVisible(hwnd).as_bool() {
+                    PostMessageW(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
+                }
+            }
+            log::debug!("FoldAll");
+        },
+        ItemKind::UnfoldAll => unsafe {
+            for id in unfolds {
+                let hwnd = GetDlgItem(mmd_window, *id as _);
+                if IsWindowVisible(hwnd).as_bool() {
+                    PostMessageW(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
+                }
+            }
+            log::debug!("UnfoldAll");
+        },
+        // Run by `Handler` step by step.
+        ItemKind::Macro(_) => {}
+        // Run by `Handler` with the resolved action.
+        ItemKind::Custom(_) => {}
+    }
+}
+
+/// An action in key_map.json with the control looked up.
+#[derive(Clone, Debug)]
+enum Custom {
+    ComboSelect {
+        id: u32,
+        pattern: String,
+    },
+    /// `last` is used when none of `items` is checked.
+    Cycle {
+        items: Vec<ItemKind>,
+        reverse: bool,
+        last: Option<usize>,
+    },
+    /// A control or a menu item recorded in learn mode.
+    Item(ItemKind),
+}
+
+impl Custom {
+    fn resolve(action: &Action, mmd_map: &MmdMap) -> Result<Self, String> {
+        match &action.kind {
+            ActionKind::ComboSelect { combo, pattern } => {
+                let id = match mmd_map.get(combo) {
+                    Some(Item {
+                        kind: ItemKind::Combo(_, id),
+                        ..
+                    }) => *id,
+                    Some(_) => return Err(format!("{} is not a combo box", combo)),
+                    None => u32::from_str_radix(combo, 16).map_err(|_| format!("unknown combo {}", combo))?,
+                };
+                Ok(Self::ComboSelect {
+                    id,
+                    pattern: pattern.clone(),
+                })
+            }
+            ActionKind::Cycle { items, reverse } => {
+                let items = items
+                    .iter()
+                    .map(|id| match mmd_map.get(id) {
+                        Some(Item {
+                            kind:
+                                kind @ (ItemKind::Button(_)
+                                | ItemKind::Menu(..)
+                                | ItemKind::MenuCommand(_)
+                                | ItemKind::MenuPath(_)),
+                            ..
+                        }) => Ok(*kind),
+                        Some(_) => Err(format!("{} is not a button or a menu", id)),
+                        None => Err(format!("unknown id {}", id)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if items.is_empty() {
+                    return Err("items is empty".to_string());
+                }
+                Ok(Self::Cycle {
+                    items,
+                    reverse: *reverse,
+                    last: None,
+                })
+            }
+            ActionKind::Control { control, class, .. } => {
+                let id = u32::from_str_radix(control, 16).map_err(|_| format!("invalid control {}", control))?;
+                match class.to_ascii_uppercase().as_str() {
+                    "BUTTON" => Ok(Self::Item(ItemKind::Button(id))),
+                    "EDIT" | "COMBOBOX" => Ok(Self::Item(ItemKind::Edit(id))),
+                    _ => Err(format!("unsupported class {}", class)),
+                }
+            }
+            ActionKind::MenuItem { command, .. } => Ok(Self::Item(ItemKind::MenuCommand(*command))),
+        }
+    }
+
+    /// Returns the item to run next if the action runs another item.
+    fn run(
+        &mut self,
+        mmd_window: HWND,
+        sub_window: Option<HWND>,
+        menu_commands: &HashMap<ItemKind, u32>,
+    ) -> Option<ItemKind> {
+        match self {
+            Self::ComboSelect { id, pattern } => unsafe {
+                let hwnd = get_control(mmd_window, sub_window, *id);
+                if !IsWindowVisible(hwnd).as_bool() || !IsWindowEnabled(hwnd).as_bool() {
+                    return None;
+                }
+                let current = SendMessageA(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
+                let size = SendMessageA(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0;
+                let found =
+                    (0..size).find(|&i| get_combo_text(hwnd, i).map_or(false, |text| match_pattern(pattern, &text)));
+                match found {
+                    Some(index) if index != current => post_set_cur_sel(hwnd, *id, mmd_window, index),
+                    Some(_) => {}
+                    None => log::debug!("ComboSelect: 0x{:x} no entry matches {}", id, pattern),
+                }
+                None
+            },
+            Self::Cycle { items, reverse, last } => {
+                let len = items.len();
+                let current = items
+                    .iter()
+                    .position(|item| is_checked(item, mmd_window, sub_window, menu_commands))
+                    .or(*last);
+                let next = match current {
+                    Some(i) if *reverse => (i + len - 1) % len,
+                    Some(i) => (i + 1) % len,
+                    None if *reverse => len - 1,
+                    None => 0,
+                };
+                *last = Some(next);
+                log::debug!("Cycle: {}", next);
+                Some(items[next])
+            }
+            Self::Item(item) => Some(*item),
+        }
+    }
+}
+
+/// The items bound to the tap, hold and double tap of one key sequence.
+#[derive(Clone, Copy, Default, Debug)]
+struct Gestures {
+    tap: Option<ItemKind>,
+    hold: Option<ItemKind>,
+    while_held: Option<ItemKind>,
+    double_tap: Option<ItemKind>,
+}
+
+impl Gestures {
+    #[inline]
+    fn has_hold(&self) -> bool {
+        self.hold.is_some() || self.while_held.is_some()
+    }
+}
+
+/// The bindings of a context, or of no context.
+#[derive(Default)]
+struct Scope {
+    handler: HashMap<KeySequence, ItemKind>,
+    prefixes: HashSet<KeySequence>,
+}
+
+impl Scope {
+    /// `label` names the context or the layer in the log.
+    fn new(mmd_map: &MmdMap, key_map: KeyMap, label: Option<&str>) -> Self {
+        let report = key_map.validate(mmd_map.iter().map(|(id, _)| id.as_str()));
+        let name = label.map_or(String::new(), |l| format!(" ({})", l));
+        for d in report.iter() {
+            match d.severity() {
+                Severity::Error => log::error!("key_map.json{}: {}", name, d),
+                Severity::Warning => log::warn!("key_map.json{}: {}", name, d),
+            }
+        }
+        let mut scope = Self::default();
+        for (k, bindings) in key_map.into_iter() {
+            if let Some(item) = mmd_map.get(&k) {
+                for v in bindings {
+                    scope.prefixes.extend(v.prefixes());
+                    scope.handler.insert(v, item.kind);
+                }
+            }
+        }
+        scope
+    }
+
+    /// The keys that follow `pending` in the bindings and the prefixes.
+    fn next_keys<'a>(&'a self, pending: &'a KeySequence) -> impl Iterator<Item = &'a Keys> + 'a {
+        self.handler
+            .keys()
+            .chain(self.prefixes.iter())
+            .filter(move |seq| seq.len() == pending.len() + 1 && seq.strokes().starts_with(pending.strokes()))
+            .filter_map(|seq| seq.last())
+    }
+
+    /// `seq` is a sequence without a trigger.
+    fn gestures(&self, seq: &KeySequence) -> Option<Gestures> {
+        let get = |t| self.handler.get(&seq.with_trigger(t)).copied();
+        let gestures = Gestures {
+            tap: get(Trigger::Tap),
+            hold: get(Trigger::Hold),
+            while_held: get(Trigger::WhileHeld),
+            double_tap: get(Trigger::DoubleTap),
+        };
+        (gestures.tap.is_some() || gestures.has_hold() || gestures.double_tap.is_some()).then_some(gestures)
+    }
+}
+
+enum Found {
+    Item(ItemKind, Option<KeyContext>),
+    Gesture(Gestures, Option<KeyContext>),
+    Prefix,
+}
+
+/// A key sequence with gestures that is still held.
+struct Held {
+    seq: KeySequence,
+    gestures: Gestures,
+    context: Option<KeyContext>,
+    sub_window: Option<HWND>,
+    hwnd: HWND,
+    time: Instant,
+    /// The hold or the double tap has run.
+    done: bool,
+}
+
+/// The bindings of a layer, looked up before any other while the layer is on.
+struct LayerScope {
+    name: String,
+    key: Keys,
+    mode: LayerMode,
+    scope: Scope,
+}
+
+/// The item run by the last key down, repeated while the key is held.
+struct Repeating {
+    vk: u32,
+    item: ItemKind,
+    context: Option<KeyContext>,
+    repeat: Repeat,
+    sub_window: Option<HWND>,
+    hwnd: HWND,
+}
+
+/// A macro waiting for a delay or a control.
+struct RunningMacro {
+    index: usize,
+    /// The next step.
+    pos: usize,
+    sub_window: Option<HWND>,
+    /// The end of the current wait.
+    deadline: Option<Instant>,
+}
+
+/// A released key sequence waiting for a second tap.
+struct Tapped {
+    seq: KeySequence,
+    gestures: Gestures,
+    context: Option<KeyContext>,
+    sub_window: Option<HWND>,
+    hwnd: HWND,
+    time: Instant,
+}
 
 pub struct Handler {
     input: Vec<u8>,
     input_keys: Keys,
-    handler: HashMap<Keys, ItemKind>,
+    scope: Scope,
+    contexts: HashMap<KeyContext, Scope>,
+    layers: Vec<LayerScope>,
+    /// Indices into `layers`, the last one on top.
+    active_layers: Vec<usize>,
+    camera_panel: Vec<u32>,
+    pending: KeySequence,
+    pending_time: Instant,
+    timing: Timing,
+    /// Keyed by the virtual key code, since another gesture can start while one is held.
+    held: HashMap<u32, Held>,
+    tapped: Option<Tapped>,
+    repeats: HashMap<ItemKind, Repeat>,
+    repeating: Option<Repeating>,
+    macros: Vec<Macro>,
+    running_macro: Option<RunningMacro>,
+    customs: Vec<Custom>,
+    menu_commands: HashMap<ItemKind, u32>,
     key_states: HashMap<u32, bool>,
+    momentary_presses: HashMap<u32, Instant>,
     folds: Vec<u32>,
     unfolds: Vec<u32>,
     tabstop: bool,
 }
 
 impl Handler {
-    pub fn new(mmd_map: &MmdMap, key_map: KeyMap) -> Self {
+    pub fn new(mmd_map: &MmdMap, macros: &[Macro], mut key_map: KeyMap, timing: Timing) -> Self {
+        // The actions in key_map.json are bound like the items in mmd_map.json.
+        let mut mmd_map = mmd_map.clone();
+        let mut customs = vec![];
+        for (id, action) in key_map.take_actions() {
+            if mmd_map.get(&id).is_some() {
+                log::error!("key_map.json: actions: {}: the id is already used", id);
+                continue;
+            }
+            match Custom::resolve(&action, &mmd_map) {
+                Ok(custom) => {
+                    customs.push(custom);
+                    mmd_map.push(
+                        id,
+                        Item {
+                            name: action.name,
+                            kind: ItemKind::Custom(customs.len() - 1),
+                        },
+                    );
+                }
+                Err(e) => log::error!("key_map.json: actions: {}: {}", id, e),
+            }
+        }
+        let mmd_map = &mmd_map;
         let mut key_states = HashMap::new();
         mmd_map
             .iter()
@@ -32,204 +558,671 @@ impl Handler {
                 folds.push(hide);
                 unfolds.push(show);
             });
-        let mut handler = HashMap::new();
-        for (k, v) in key_map.into_iter() {
-            if let Some(item) = mmd_map.get(&k) {
-                handler.insert(v, item.kind);
-            } else {
-                log::error!("handler.insert error: {}", k);
+        let repeats = key_map
+            .repeats()
+            .filter_map(|(id, repeat)| mmd_map.get(id).map(|item| (item.kind, *repeat)))
+            .collect::<HashMap<_, _>>();
+        let contexts = key_map
+            .take_contexts()
+            .into_iter()
+            .map(|(c, m)| (c, Scope::new(mmd_map, m, Some(c.as_str()))))
+            .collect::<HashMap<_, _>>();
+        let layers = key_map
+            .take_layers()
+            .into_iter()
+            .filter(|(_, layer)| !layer.key.is_empty())
+            .map(|(name, layer)| LayerScope {
+                scope: Scope::new(mmd_map, layer.key_map, Some(&format!("layer {}", name))),
+                name,
+                key: layer.key,
+                mode: layer.mode,
+            })
+            .collect::<Vec<_>>();
+        let mut scope = Scope::new(mmd_map, key_map, None);
+        for layer in layers.iter() {
+            if scope.handler.contains_key(&layer.key.clone().into()) {
+                log::warn!("key_map.json: {} is the key of layer {}", layer.key, layer.name);
             }
         }
-        handler.insert(Keys::from_slice(&[VK_CONTROL.0 as _]), ItemKind::Key(VK_CONTROL.0 as _));
-        handler.insert(Keys::from_slice(&[VK_SHIFT.0 as _]), ItemKind::Key(VK_SHIFT.0 as _));
+        scope.handler.insert(
+            Keys::from_slice(&[VK_CONTROL.0 as _]).into(),
+            ItemKind::Key(VK_CONTROL.0 as _),
+        );
+        scope.handler.insert(
+            Keys::from_slice(&[VK_SHIFT.0 as _]).into(),
+            ItemKind::Key(VK_SHIFT.0 as _),
+        );
         key_states.insert(VK_CONTROL.0 as _, false);
         key_states.insert(VK_SHIFT.0 as _, false);
+        // The camera panel is shown only in camera edit mode.
+        let camera_panel = mmd_map
+            .get("CameraFold")
+            .and_then(|item| item.kind.as_fold())
+            .map_or(vec![], |(hide, show)| vec![hide, show]);
         Self {
             input: vec![0; 256],
             input_keys: Keys::with_capacity(3),
-            handler,
+            scope,
+            contexts,
+            layers,
+            active_layers: vec![],
+            camera_panel,
+            pending: KeySequence::new(),
+            pending_time: Instant::now(),
+            timing,
+            held: HashMap::new(),
+            tapped: None,
+            repeats,
+            repeating: None,
+            macros: macros.to_vec(),
+            running_macro: None,
+            customs,
+            menu_commands: mmd_map.menu_commands().clone(),
             key_states,
+            momentary_presses: HashMap::new(),
             folds,
             unfolds,
             tabstop: false,
         }
     }
 
-    pub fn key_down(&mut self, vk: u32, mmd_window: HWND, sub_window: Option<HWND>, hwnd: HWND) {
-        fn handle(
-            item: &ItemKind,
-            key_states: &mut HashMap<u32, bool>,
-            folds: &[u32],
-            unfolds: &[u32],
-            mmd_window: HWND,
-            sub_window: Option<HWND>,
-            hwnd: HWND,
-        ) {
-            if get_class_name(hwnd).to_ascii_uppercase() == "EDIT" {
-                unsafe {
-                    if item == &ItemKind::KillFocus {
-                        SetFocus(mmd_window);
-                        log::debug!("KillFocus")
-                    }
-                }
-                return;
-            }
-            match item {
-                ItemKind::Key(k) => {
-                    if key_states.get(k).is_some() && *k != VK_SHIFT.0 as u32 && *k != VK_CONTROL.0 as u32 {
-                        *key_states.get_mut(&(VK_SHIFT.0 as u32)).unwrap() = false;
-                        *key_states.get_mut(&(VK_CONTROL.0 as u32)).unwrap() = false;
-                    }
-                    if let Some(ks) = key_states.get_mut(k) {
-                        *ks = true;
-                        log::debug!("Key: 0x{:x}", k);
-                    }
-                }
-                ItemKind::Button(id) => unsafe {
-                    let hwnd = GetDlgItem(mmd_window, *id as _);
-                    let hwnd = hwnd
-                        .is_invalid()
-                        .then(|| sub_window.map(|sw| GetDlgItem(sw, *id as _)))
-                        .flatten()
-                        .unwrap_or(hwnd);
-                    if IsWindowVisible(hwnd).as_bool() && IsWindowEnabled(hwnd).as_bool() {
-                        PostMessageA(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
-                        log::debug!("Button: 0x{:x}", id);
-                    }
-                },
-                ItemKind::Edit(id) => unsafe {
-                    let hwnd = GetDlgItem(mmd_window, *id as _);
-                    let hwnd = hwnd
-                        .is_invalid()
-                        .then(|| sub_window.map(|sw| GetDlgItem(sw, *id as _)))
-                        .flatten()
-                        .unwrap_or(hwnd);
-                    if IsWindowVisible(hwnd).as_bool() && IsWindowEnabled(hwnd).as_bool() {
-                        SetFocus(hwnd);
-                        log::debug!("Edit: 0x{:x}", id);
-                    }
-                },
-                ItemKind::Combo(dir, id) => unsafe {
-                    #[inline]
-                    unsafe fn post_set_cur_sel(hwnd: HWND, id: u32, parent: HWND, index: isize) {
-                        PostMessageW(hwnd, CB_SETCURSEL, WPARAM(index as _), LPARAM(0));
-                        PostMessageW(
-                            parent,
-                            WM_COMMAND,
-                            WPARAM(((id & 0xffff) | (CBN_SELCHANGE << 16)) as _),
-                            LPARAM(hwnd.0),
-                        );
-                        log::debug!("Combo: 0x{:x}", id);
-                    }
+    /// The contexts in the order of precedence.
+    fn active_contexts(&self, mmd_window: HWND, hwnd: HWND) -> Vec<KeyContext> {
+        let mut v = Vec::with_capacity(2);
+        if self.contexts.is_empty() {
+            return v;
+        }
+        if is_edit(hwnd) {
+            v.push(KeyContext::Edit);
+        }
+        let camera = self
+            .camera_panel
+            .iter()
+            .any(|id| unsafe { IsWindowVisible(GetDlgItem(mmd_window, *id as _)).as_bool() });
+        v.push(if camera { KeyContext::Camera } else { KeyContext::Model });
+        v
+    }
 
-                    let hwnd = GetDlgItem(mmd_window, *id as _);
-                    let hwnd = hwnd
-                        .is_invalid()
-                        .then(|| sub_window.map(|sw| GetDlgItem(sw, *id as _)))
-                        .flatten()
-                        .unwrap_or(hwnd);
-                    if !IsWindowVisible(hwnd).as_bool() || !IsWindowEnabled(hwnd).as_bool() {
-                        return;
-                    }
-                    let index = SendMessageA(hwnd, CB_GETCURSEL, WPARAM(0), LPARAM(0)).0;
-                    let size = SendMessageA(hwnd, CB_GETCOUNT, WPARAM(0), LPARAM(0)).0;
-                    match dir {
-                        ComboDir::Prev if index >= 1 => post_set_cur_sel(hwnd, *id, mmd_window, index - 1),
-                        ComboDir::Next if index < size - 1 => post_set_cur_sel(hwnd, *id, mmd_window, index + 1),
-                        _ => {}
-                    }
-                },
-                ItemKind::Menu(index, sub_index) => unsafe {
-                    let m = GetSubMenu(GetMenu(mmd_window), *index as _);
-                    let state = GetMenuState(m, *sub_index as _, MF_BYPOSITION);
-                    if (state & MFS_DISABLED.0) == 0 {
-                        PostMessageA(
-                            mmd_window,
-                            WM_COMMAND,
-                            WPARAM(GetMenuItemID(m, *sub_index as _) as _),
-                            LPARAM(0),
-                        );
-                        log::debug!("Menu: {}, {}", index, sub_index);
-                    }
-                },
-                ItemKind::Fold(hide_id, show_id) => unsafe {
-                    let hide = GetDlgItem(mmd_window, *hide_id as _);
-                    if IsWindowVisible(hide).as_bool() {
-                        PostMessageW(hide, BM_CLICK, WPARAM(0), LPARAM(0));
-                        log::debug!("Fold: 0x{:x}", hide_id);
-                    } else {
-                        let show = GetDlgItem(mmd_window, *show_id as _);
-                        PostMessageW(show, BM_CLICK, WPARAM(0), LPARAM(0));
-                        log::debug!("Fold: 0x{:x}", show_id);
-                    }
-                },
-                ItemKind::KillFocus => unsafe {
-                    SetFocus(mmd_window);
-                    log::debug!("KillFocus");
-                },
-                ItemKind::FoldAll => unsafe {
-                    for id in folds {
-                        let hwnd = GetDlgItem(mmd_window, *id as _);
-                        if IsWindowVisible(hwnd).as_bool() {
-                            PostMessageW(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
-                        }
-                    }
-                    log::debug!("FoldAll");
-                },
-                ItemKind::UnfoldAll => unsafe {
-                    for id in unfolds {
-                        let hwnd = GetDlgItem(mmd_window, *id as _);
-                        if IsWindowVisible(hwnd).as_bool() {
-                            PostMessageW(hwnd, BM_CLICK, WPARAM(0), LPARAM(0));
-                        }
-                    }
-                    log::debug!("UnfoldAll");
-                },
+    /// The active layers from the top first, then the active contexts, then the bindings without a context.
+    fn scopes<'a>(&'a self, contexts: &'a [KeyContext]) -> impl Iterator<Item = (Option<KeyContext>, &'a Scope)> + 'a {
+        self.active_layers
+            .iter()
+            .rev()
+            .map(move |&i| (None, &self.layers[i].scope))
+            .chain(
+                contexts
+                    .iter()
+                    .filter_map(move |c| self.contexts.get(c).map(|scope| (Some(*c), scope))),
+            )
+            .chain(std::iter::once((None, &self.scope)))
+    }
+
+    fn find(&self, seq: &KeySequence, contexts: &[KeyContext]) -> Option<Found> {
+        for (c, scope) in self.scopes(contexts) {
+            if let Some(item) = scope.handler.get(seq) {
+                return Some(Found::Item(*item, c));
+            }
+            if let Some(gestures) = scope.gestures(seq) {
+                return Some(Found::Gesture(gestures, c));
+            }
+            if scope.prefixes.contains(seq) {
+                return Some(Found::Prefix);
             }
         }
+        None
+    }
 
+    /// Returns `true` if the input was handled by a binding.
+    /// `repeat` is `true` for the auto-repeat of Windows.
+    pub fn key_down(&mut self, vk: u32, repeat: bool, mmd_window: HWND, sub_window: Option<HWND>, hwnd: HWND) -> bool {
+        // The auto-repeat of a held gesture.
+        if self.held.contains_key(&vk) {
+            return true;
+        }
+        if repeat {
+            if let Some(r) = self.repeating.as_ref().filter(|r| r.vk == vk) {
+                if r.repeat == Repeat::Os {
+                    let (item, context, sub_window, hwnd) = (r.item, r.context, r.sub_window, r.hwnd);
+                    self.run(item, context, mmd_window, sub_window, hwnd);
+                }
+                return true;
+            }
+            // A prefix held past the repeat delay keeps the sequence pending.
+            let prefix = self.pending.last().map_or(false, |keys| keys.contains(vk));
+            if prefix && self.pending_time.elapsed() <= self.timing.sequence_timeout {
+                return true;
+            }
+        }
         get_keyboard_state(&mut self.input);
         self.input_keys.keyboard_state(&self.input);
+        if is_wheel(vk) || is_mouse_button(vk) {
+            self.input_keys.push(vk);
+        }
         log::debug!("key_down input_keys = {:?}", self.input_keys);
-        if get_class_name(hwnd).to_ascii_uppercase() == "EDIT" {
+        if is_edit(hwnd) {
             if self.input_keys == Keys::from_slice(&[VK_TAB.0 as u32]) {
                 self.tabstop = true;
-                return;
+                return true;
+            }
+        }
+        let layer = self
+            .input_keys
+            .side_variants(&self.input)
+            .iter()
+            .find_map(|keys| self.layers.iter().position(|layer| &layer.key == keys));
+        if let Some(index) = layer {
+            self.switch_layer(index, repeat);
+            return true;
+        }
+        // The key of a layer being held is not a part of the bindings in the layer.
+        for &i in self.active_layers.iter() {
+            if self.layers[i].mode == LayerMode::Momentary {
+                self.input_keys.remove(&self.layers[i].key);
+            }
+        }
+        if !self.pending.is_empty() && self.pending_time.elapsed() > self.timing.sequence_timeout {
+            log::debug!("sequence timeout: {}", self.pending);
+            self.pending.clear();
+        }
+        // Pressing a modifier alone neither continues nor cancels a pending sequence.
+        let pending = if self.input_keys.is_modifiers_only() {
+            KeySequence::new()
+        } else {
+            std::mem::take(&mut self.pending)
+        };
+        let contexts = self.active_contexts(mmd_window, hwnd);
+        let variants = self.input_keys.side_variants(&self.input);
+        // Without an exact match, the most specific binding whose keys are all held is used.
+        let candidates = self
+            .scopes(&contexts)
+            .flat_map(|(_, scope)| scope.next_keys(&pending))
+            .collect::<Vec<_>>();
+        let resolved = resolve_chord(&variants[0], vk, candidates).cloned();
+        // Side-specific bindings such as "RCtrl+Left" take precedence over "Ctrl+Left".
+        for keys in variants.into_iter().chain(resolved) {
+            let mut seq = pending.clone();
+            seq.push(keys);
+            match self.find(&seq, &contexts) {
+                Some(Found::Prefix) => {
+                    log::debug!("sequence pending: {}", seq);
+                    self.pending = seq;
+                    self.pending_time = Instant::now();
+                    return true;
+                }
+                Some(Found::Item(item, context)) => {
+                    self.press_item(vk, item, context, mmd_window, sub_window, hwnd);
+                    return true;
+                }
+                Some(Found::Gesture(gestures, context)) => {
+                    self.gesture_down(seq, vk, gestures, context, mmd_window, sub_window, hwnd);
+                    return true;
+                }
+                None => {}
+            }
+        }
+        if !pending.is_empty() {
+            log::debug!("sequence canceled: {} {}", pending, self.input_keys);
+        }
+        false
+    }
+
+    /// The auto-repeat of a layer key is ignored, or it would turn a toggled layer back on.
+    fn switch_layer(&mut self, index: usize, repeat: bool) {
+        if repeat {
+            return;
+        }
+        let active = self.active_layers.iter().position(|&i| i == index);
+        let layer = &self.layers[index];
+        match (layer.mode, active) {
+            (LayerMode::Momentary, Some(_)) => {}
+            (_, None) => {
+                log::debug!("layer on: {}", layer.name);
+                self.active_layers.push(index);
+            }
+            (_, Some(i)) => {
+                log::debug!("layer off: {}", layer.name);
+                self.active_layers.remove(i);
             }
         }
-        if let Some(item) = self.handler.get(&self.input_keys) {
-            handle(
+    }
+
+    /// A one-shot layer is turned off by the action run next.
+    fn release_one_shot(&mut self) {
+        let layers = &self.layers;
+        self.active_layers.retain(|&i| layers[i].mode != LayerMode::OneShot);
+    }
+
+    /// Runs an item bound to the pressed keys and starts repeating it as set in key_map.json.
+    fn press_item(
+        &mut self,
+        vk: u32,
+        item: ItemKind,
+        context: Option<KeyContext>,
+        mmd_window: HWND,
+        sub_window: Option<HWND>,
+        hwnd: HWND,
+    ) {
+        self.flush_tap(mmd_window);
+        self.stop_repeat(mmd_window);
+        self.release_one_shot();
+        self.run(item, context, mmd_window, sub_window, hwnd);
+        if is_wheel(vk) {
+            self.release_momentary(item);
+            return;
+        }
+        let repeat = self.repeats.get(&item).copied().unwrap_or_default();
+        if let Repeat::Custom { delay, .. } = repeat {
+            // A key item is pressed again on every repeat, so it is released in between.
+            self.release_momentary(item);
+            unsafe {
+                SetTimer(mmd_window, REPEAT_TIMER_ID, delay.max(1), None);
+            }
+        }
+        self.repeating = Some(Repeating {
+            vk,
+            item,
+            context,
+            repeat,
+            sub_window,
+            hwnd,
+        });
+    }
+
+    fn stop_repeat(&mut self, mmd_window: HWND) {
+        if let Some(r) = self.repeating.take() {
+            if let Repeat::Custom { .. } = r.repeat {
+                unsafe {
+                    KillTimer(mmd_window, REPEAT_TIMER_ID);
+                }
+            }
+        }
+    }
+
+    /// Called on `WM_TIMER` with `REPEAT_TIMER_ID`.
+    pub fn repeat_timer(&mut self, mmd_window: HWND) {
+        let (item, context, interval, sub_window, hwnd) = match self.repeating.as_ref() {
+            Some(&Repeating {
                 item,
-                &mut self.key_states,
-                &self.folds,
-                &self.unfolds,
-                mmd_window,
+                context,
+                repeat: Repeat::Custom { interval, .. },
                 sub_window,
                 hwnd,
-            );
-            return;
+                ..
+            }) => (item, context, interval, sub_window, hwnd),
+            _ => {
+                unsafe {
+                    KillTimer(mmd_window, REPEAT_TIMER_ID);
+                }
+                return;
+            }
+        };
+        self.run(item, context, mmd_window, sub_window, hwnd);
+        self.release_momentary(item);
+        unsafe {
+            SetTimer(mmd_window, REPEAT_TIMER_ID, interval.max(1), None);
         }
-        self.input_keys.vk(vk);
-        if let Some(item) = self.handler.get(&self.input_keys) {
-            handle(
-                item,
-                &mut self.key_states,
-                &self.folds,
-                &self.unfolds,
-                mmd_window,
+    }
+
+    /// Starts a gesture, or runs the double tap when `seq` was tapped just before.
+    #[allow(clippy::too_many_arguments)]
+    fn gesture_down(
+        &mut self,
+        seq: KeySequence,
+        vk: u32,
+        gestures: Gestures,
+        context: Option<KeyContext>,
+        mmd_window: HWND,
+        sub_window: Option<HWND>,
+        hwnd: HWND,
+    ) {
+        let double_tap = self
+            .tapped
+            .as_ref()
+            .filter(|t| t.seq == seq && t.time.elapsed() <= self.timing.double_tap_interval)
+            .and(gestures.double_tap);
+        if double_tap.is_some() {
+            self.tapped = None;
+        } else {
+            self.flush_tap(mmd_window);
+        }
+        self.release_one_shot();
+        if let Some(item) = double_tap {
+            log::debug!("double tap: {}", seq);
+            self.run(item, context, mmd_window, sub_window, hwnd);
+            self.release_momentary(item);
+        }
+        log::debug!("gesture pending: {}", seq);
+        self.held.insert(
+            vk,
+            Held {
+                seq,
+                gestures,
+                context,
                 sub_window,
                 hwnd,
-            );
+                time: Instant::now(),
+                done: double_tap.is_some(),
+            },
+        );
+        // The wheel has no key up, so it is released at once.
+        if is_wheel(vk) {
+            let held = self.held.remove(&vk).unwrap();
+            self.gesture_up(held, mmd_window);
+        }
+        self.schedule(mmd_window);
+    }
+
+    /// Runs a pending tap when another input comes before the double tap interval ends.
+    fn flush_tap(&mut self, mmd_window: HWND) {
+        if let Some(tapped) = self.tapped.take() {
+            if let Some(item) = tapped.gestures.tap {
+                log::debug!("tap: {}", tapped.seq);
+                self.run(item, tapped.context, mmd_window, tapped.sub_window, tapped.hwnd);
+                self.release_momentary(item);
+            }
         }
     }
 
-    pub fn key_up(&mut self, vk: u32) {
+    fn gesture_up(&mut self, held: Held, mmd_window: HWND) {
+        let gestures = held.gestures;
+        if held.done {
+            // A key item is released by `key_up` as for any other binding.
+            if let Some(item) = gestures.while_held.filter(|item| item.as_key().is_none()) {
+                log::debug!("while held end: {}", held.seq);
+                self.run(item, held.context, mmd_window, held.sub_window, held.hwnd);
+            }
+            return;
+        }
+        // Released after the hold time, which is not a tap.
+        // The hold runs here when the key up comes before `WM_TIMER`, which has the lowest priority.
+        if held.time.elapsed() >= self.timing.hold_time {
+            match gestures.while_held.or(gestures.hold) {
+                Some(item) => {
+                    log::debug!("hold: {}", held.seq);
+                    self.run(item, held.context, mmd_window, held.sub_window, held.hwnd);
+                    if gestures.while_held.is_some() && item.as_key().is_none() {
+                        log::debug!("while held end: {}", held.seq);
+                        self.run(item, held.context, mmd_window, held.sub_window, held.hwnd);
+                    } else {
+                        self.release_momentary(item);
+                    }
+                }
+                None => log::debug!("tap canceled: {}", held.seq),
+            }
+            return;
+        }
+        if gestures.double_tap.is_some() {
+            self.tapped = Some(Tapped {
+                seq: held.seq,
+                gestures,
+                context: held.context,
+                sub_window: held.sub_window,
+                hwnd: held.hwnd,
+                time: Instant::now(),
+            });
+        } else if let Some(item) = gestures.tap {
+            log::debug!("tap: {}", held.seq);
+            self.run(item, held.context, mmd_window, held.sub_window, held.hwnd);
+            self.release_momentary(item);
+        }
+    }
+
+    /// Called on `WM_TIMER` with `GESTURE_TIMER_ID`.
+    pub fn timer(&mut self, mmd_window: HWND) {
+        let hold_time = self.timing.hold_time;
+        let holds = self
+            .held
+            .values_mut()
+            .filter(|held| !held.done && held.time.elapsed() >= hold_time)
+            .filter_map(|held| {
+                let item = held.gestures.while_held.or(held.gestures.hold)?;
+                held.done = true;
+                log::debug!("hold: {}", held.seq);
+                Some((item, held.context, held.sub_window, held.hwnd))
+            })
+            .collect::<Vec<_>>();
+        for (item, context, sub_window, hwnd) in holds {
+            self.run(item, context, mmd_window, sub_window, hwnd);
+        }
+        if self
+            .tapped
+            .as_ref()
+            .map_or(false, |t| t.time.elapsed() > self.timing.double_tap_interval)
+        {
+            self.flush_tap(mmd_window);
+        }
+        self.schedule(mmd_window);
+    }
+
+    /// Sets the timer to the next hold or end of a double tap interval, or stops it.
+    fn schedule(&self, mmd_window: HWND) {
+        let hold = self
+            .held
+            .values()
+            .filter(|held| !held.done && held.gestures.has_hold())
+            .map(|held| self.timing.hold_time.saturating_sub(held.time.elapsed()));
+        let tap = self
+            .tapped
+            .as_ref()
+            .map(|t| self.timing.double_tap_interval.saturating_sub(t.time.elapsed()));
+        unsafe {
+            match hold.into_iter().chain(tap).min() {
+                Some(d) => {
+                    SetTimer(mmd_window, GESTURE_TIMER_ID, d.as_millis().max(1) as _, None);
+                }
+                None => {
+                    KillTimer(mmd_window, GESTURE_TIMER_ID);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn run(
+        &mut self,
+        item: ItemKind,
+        context: Option<KeyContext>,
+        mmd_window: HWND,
+        sub_window: Option<HWND>,
+        hwnd: HWND,
+    ) {
+        if let ItemKind::Macro(index) = item {
+            if context == Some(KeyContext::Edit) || !is_edit(hwnd) {
+                self.start_macro(index, mmd_window, sub_window);
+            }
+            return;
+        }
+        if let ItemKind::Custom(index) = item {
+            if context == Some(KeyContext::Edit) || !is_edit(hwnd) {
+                if let Some(item) = self.customs[index].run(mmd_window, sub_window, &self.menu_commands) {
+                    handle(
+                        &item,
+                        context,
+                        &mut self.key_states,
+                        &self.folds,
+                        &self.unfolds,
+                        &self.menu_commands,
+                        mmd_window,
+                        sub_window,
+                        hwnd,
+                    );
+                }
+            }
+            return;
+        }
+        handle(
+            &item,
+            context,
+            &mut self.key_states,
+            &self.folds,
+            &self.unfolds,
+            &self.menu_commands,
+            mmd_window,
+            sub_window,
+            hwnd,
+        );
+    }
+
+    /// Cancels the macro still running.
+    fn start_macro(&mut self, index: usize, mmd_window: HWND, sub_window: Option<HWND>) {
+        let m = match self.macros.get(index) {
+            Some(m) => m,
+            None => return,
+        };
+        if let Some(running) = self.running_macro.take() {
+            log::debug!("macro canceled: {}", self.macros[running.index].id);
+            unsafe {
+                KillTimer(mmd_window, MACRO_TIMER_ID);
+            }
+        }
+        log::debug!("macro: {}", m.id);
+        self.running_macro = Some(RunningMacro {
+            index,
+            pos: 0,
+            sub_window,
+            deadline: None,
+        });
+        self.step_macro(mmd_window);
+    }
+
+    /// Runs the steps until a delay, a wait or the end.
+    fn step_macro(&mut self, mmd_window: HWND) {
+        while let Some(running) = self.running_macro.as_mut() {
+            let step = match self.macros[running.index].steps.get(running.pos) {
+                Some(step) => *step,
+                None => {
+                    log::debug!("macro end: {}", self.macros[running.index].id);
+                    self.running_macro = None;
+                    return;
+                }
+            };
+            let sub_window = running.sub_window;
+            match step {
+                Step::Item(item) => {
+                    running.pos += 1;
+                    // The following steps run even after a step focuses a text box.
+                    let hwnd = unsafe { GetFocus() };
+                    handle(
+                        &item,
+                        Some(KeyContext::Edit),
+                        &mut self.key_states,
+                        &self.folds,
+                        &self.unfolds,
+                        &self.menu_commands,
+                        mmd_window,
+                        sub_window,
+                        hwnd,
+                    );
+                    self.release_momentary(item);
+                }
+                Step::Delay(delay) => {
+                    running.pos += 1;
+                    unsafe {
+                        SetTimer(mmd_window, MACRO_TIMER_ID, delay.as_millis().max(1) as _, None);
+                    }
+                    return;
+                }
+                Step::WaitEnabled { id, timeout } => {
+                    let enabled = unsafe {
+                        let hwnd = get_control(mmd_window, sub_window, id);
+                        IsWindowVisible(hwnd).as_bool() && IsWindowEnabled(hwnd).as_bool()
+                    };
+                    if enabled {
+                        running.pos += 1;
+                        running.deadline = None;
+                        continue;
+                    }
+                    let deadline = *running.deadline.get_or_insert_with(|| Instant::now() + timeout);
+                    if Instant::now() >= deadline {
+                        log::warn!("macro {}: 0x{:x} is not enabled", self.macros[running.index].id, id);
+                        self.running_macro = None;
+                        return;
+                    }
+                    unsafe {
+                        SetTimer(mmd_window, MACRO_TIMER_ID, MACRO_POLL_INTERVAL, None);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Called on `WM_TIMER` with `MACRO_TIMER_ID`.
+    pub fn macro_timer(&mut self, mmd_window: HWND) {
+        unsafe {
+            KillTimer(mmd_window, MACRO_TIMER_ID);
+        }
+        self.step_macro(mmd_window);
+    }
+
+    /// A key item pressed by an input without a key up is released after `MOMENTARY_PRESS_DURATION`.
+    fn release_momentary(&mut self, item: ItemKind) {
+        if let ItemKind::Key(k) = item {
+            if let Some(ks) = self.key_states.get_mut(&k) {
+                *ks = false;
+                self.momentary_presses.insert(k, Instant::now());
+            }
+        }
+    }
+
+    /// Runs an item held until `release`, such as from a MIDI note.
+    pub fn press(&mut self, item: ItemKind, mmd_window: HWND, sub_window: Option<HWND>, hwnd: HWND) {
+        self.run(item, None, mmd_window, sub_window, hwnd);
+    }
+
+    #[inline]
+    pub fn release(&mut self, item: ItemKind) {
+        if let ItemKind::Key(k) = item {
+            if let Some(ks) = self.key_states.get_mut(&k) {
+                *ks = false;
+            }
+        }
+    }
+
+    /// Runs an item from an input without a key up, such as a MIDI knob.
+    pub fn trigger(&mut self, item: ItemKind, mmd_window: HWND, sub_window: Option<HWND>, hwnd: HWND) {
+        self.press(item, mmd_window, sub_window, hwnd);
+        self.release_momentary(item);
+    }
+
+    pub fn key_up(&mut self, vk: u32, mmd_window: HWND) {
+        // Releasing a modifier changes the keys, so the auto-repeat is looked up again.
+        let released = self
+            .repeating
+            .as_ref()
+            .map_or(false, |r| r.vk == vk || Keys::from_slice(&[vk]).is_modifiers_only());
+        if released {
+            self.stop_repeat(mmd_window);
+        }
+        if let Some(held) = self.held.remove(&vk) {
+            self.gesture_up(held, mmd_window);
+            self.schedule(mmd_window);
+        }
         get_keyboard_state(&mut self.input);
         self.input_keys.keyboard_state(&self.input);
         log::debug!("key_up input_keys = {:?}", self.input_keys);
-        for (keys, kind) in self.handler.iter() {
+        let variants = self.input_keys.side_variants(&self.input);
+        let layers = &self.layers;
+        self.active_layers.retain(|&i| {
+            let on = layers[i].mode != LayerMode::Momentary || variants.iter().any(|v| layers[i].key.is_included(v));
+            if !on {
+                log::debug!("layer off: {}", layers[i].name);
+            }
+            on
+        });
+        let bindings = self
+            .layers
+            .iter()
+            .map(|layer| &layer.scope)
+            .chain(self.contexts.values())
+            .chain(std::iter::once(&self.scope))
+            .flat_map(|scope| scope.handler.iter());
+        for (seq, kind) in bindings {
             if let ItemKind::Key(k) = kind {
-                if !keys.is_included(&self.input_keys) {
+                let held = seq
+                    .last()
+                    .map_or(false, |keys| variants.iter().any(|v| keys.is_included(v)));
+                if !held {
                     if let Some(ks) = self.key_states.get_mut(k) {
                         *ks = false;
                     }
@@ -245,7 +1238,11 @@ impl Handler {
         if vk == VK_TAB.0 as u32 && self.tabstop {
             return true;
         }
-        *self.key_states.get(&vk).unwrap_or(&false)
+        let wheel_pressed = self
+            .momentary_presses
+            .get(&vk)
+            .map_or(false, |t| t.elapsed() < MOMENTARY_PRESS_DURATION);
+        wheel_pressed || *self.key_states.get(&vk).unwrap_or(&false)
     }
 }
 
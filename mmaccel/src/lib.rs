@@ -7,7 +7,10 @@ mod context;
 mod file_monitor;
 mod handler;
 mod injection;
+mod macros;
 mod menu;
+mod midi;
+mod midi_input;
 mod mmd;
 mod mmd_map;
 
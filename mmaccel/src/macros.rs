@@ -0,0 +1,214 @@
+use crate::mmd_map::*;
+use std::time::Duration;
+
+/// How long a macro waits after a key step, so that MMD sees each press and release of the key.
+/// The key is pressed for `MOMENTARY_PRESS_DURATION` of the handler and released for the rest.
+pub const KEY_STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+#[inline]
+fn default_timeout() -> u64 {
+    1000
+}
+
+/// A step written in macros.json.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum StepDef {
+    /// An mmd_map ID such as `"KeyCopy"`.
+    Item(String),
+    /// `{ "delay": 100 }` in milliseconds.
+    Delay { delay: u64 },
+    /// `{ "wait_enabled": "KeyPaste", "timeout": 1000 }` waits until the control of the item is enabled.
+    WaitEnabled {
+        wait_enabled: String,
+        #[serde(default = "default_timeout")]
+        timeout: u64,
+    },
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct MacroDef {
+    pub id: String,
+    pub name: String,
+    steps: Vec<StepDef>,
+}
+
+/// The contents of macros.json, in the order shown in key_config.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(transparent)]
+pub struct MacroDefs(pub Vec<MacroDef>);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    Item(ItemKind),
+    Delay(Duration),
+    /// `id` is a control ID. The macro stops when `timeout` passes.
+    WaitEnabled {
+        id: u32,
+        timeout: Duration,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub id: String,
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+/// A macro that cannot be used.
+#[derive(Debug)]
+pub struct MacroError {
+    pub id: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for MacroError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.id, self.message)
+    }
+}
+
+impl std::error::Error for MacroError {}
+
+impl MacroDefs {
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Looks up the steps in `mmd_map`. A macro cannot be a step of another macro.
+    /// A key step is followed by a delay of `KEY_STEP_INTERVAL`.
+    pub fn resolve(&self, mmd_map: &MmdMap) -> (Vec<Macro>, Vec<MacroError>) {
+        let mut macros: Vec<Macro> = vec![];
+        let mut errors = vec![];
+        for def in self.0.iter() {
+            let error = |message: String| MacroError {
+                id: def.id.clone(),
+                message,
+            };
+            if mmd_map.get(&def.id).is_some() || macros.iter().any(|m| m.id == def.id) {
+                errors.push(error("the id is already used".to_string()));
+                continue;
+            }
+            let steps = def
+                .steps
+                .iter()
+                .map(|step| match step {
+                    StepDef::Item(id) => match mmd_map.get(id) {
+                        Some(Item {
+                            kind: ItemKind::Macro(_),
+                            ..
+                        }) => Err(error(format!("{} is a macro", id))),
+                        Some(Item {
+                            kind: kind @ ItemKind::Key(_),
+                            ..
+                        }) => Ok(vec![Step::Item(*kind), Step::Delay(KEY_STEP_INTERVAL)]),
+                        Some(item) => Ok(vec![Step::Item(item.kind)]),
+                        None => Err(error(format!("unknown id {}", id))),
+                    },
+                    StepDef::Delay { delay } => Ok(vec![Step::Delay(Duration::from_millis(*delay))]),
+                    StepDef::WaitEnabled { wait_enabled, timeout } => {
+                        match mmd_map.get(wait_enabled).and_then(|item| item.kind.control_id()) {
+                            Some(id) => Ok(vec![Step::WaitEnabled {
+                                id,
+                                timeout: Duration::from_millis(*timeout),
+                            }]),
+                            None => Err(error(format!("{} has no control", wait_enabled))),
+                        }
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|steps| steps.concat());
+            match steps {
+                Ok(steps) => macros.push(Macro {
+                    id: def.id.clone(),
+                    name: def.name.clone(),
+                    steps,
+                }),
+                Err(e) => errors.push(e),
+            }
+        }
+        (macros, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_macros_test() {
+        let (mmd_map, _) = MmdMap::load("src/mmd_map.json").unwrap();
+        let defs: MacroDefs = serde_json::from_str(
+            r#"[
+                {
+                    "id": "CopyNext",
+                    "name": "次のフレームにコピー",
+                    "steps": [
+                        "KeyCopy",
+                        "FrameNext",
+                        { "delay": 50 },
+                        { "wait_enabled": "KeyPaste" },
+                        "KeyPaste"
+                    ]
+                },
+                { "id": "Undo", "name": "hoge", "steps": [] },
+                { "id": "Unknown", "name": "hoge", "steps": [ "Hoge" ] },
+                { "id": "Wait", "name": "hoge", "steps": [ { "wait_enabled": "FramePrev", "timeout": 10 } ] },
+                { "id": "CopyNext", "name": "hoge", "steps": [] }
+            ]"#,
+        )
+        .unwrap();
+        let (macros, errors) = defs.resolve(&mmd_map);
+        assert!(macros.len() == 1);
+        assert!(
+            macros[0].steps
+                == [
+                    Step::Item(ItemKind::Button(0x1a4)),
+                    Step::Item(ItemKind::Key(0x27)),
+                    Step::Delay(KEY_STEP_INTERVAL),
+                    Step::Delay(Duration::from_millis(50)),
+                    Step::WaitEnabled {
+                        id: 0x1a5,
+                        timeout: Duration::from_millis(1000)
+                    },
+                    Step::Item(ItemKind::Button(0x1a5)),
+                ]
+        );
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert!(
+            errors
+                == [
+                    "Undo: the id is already used",
+                    "Unknown: unknown id Hoge",
+                    "Wait: FramePrev has no control",
+                    "CopyNext: the id is already used",
+                ]
+        );
+    }
+
+    #[test]
+    fn key_steps_test() {
+        let (mmd_map, _) = MmdMap::load("src/mmd_map.json").unwrap();
+        let defs: MacroDefs = serde_json::from_str(
+            r#"[ { "id": "Next3", "name": "3フレーム次", "steps": [ "FrameNext", "FrameNext", "FrameNext", "KeyCopy" ] } ]"#,
+        )
+        .unwrap();
+        let (macros, errors) = defs.resolve(&mmd_map);
+        assert!(errors.is_empty());
+        // Each press is released before the next step, so that the presses do not run together.
+        assert!(
+            macros[0].steps
+                == [
+                    Step::Item(ItemKind::Key(0x27)),
+                    Step::Delay(KEY_STEP_INTERVAL),
+                    Step::Item(ItemKind::Key(0x27)),
+                    Step::Delay(KEY_STEP_INTERVAL),
+                    Step::Item(ItemKind::Key(0x27)),
+                    Step::Delay(KEY_STEP_INTERVAL),
+                    Step::Item(ItemKind::Button(0x1a4)),
+                ]
+        );
+    }
+}
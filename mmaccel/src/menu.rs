@@ -1,11 +1,14 @@
 #![allow(clippy::mem_discriminant_non_enum)]
 
+use crate::mmd_map::{menu_caption, MenuEntry, MenuTable};
 use crate::*;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum MenuItemType {
     Item,
     WithCheck(bool),
+    /// The index in the radio group.
+    Radio(usize),
 }
 
 impl MenuItemType {
@@ -17,6 +20,15 @@ impl MenuItemType {
             None
         }
     }
+
+    #[inline]
+    pub fn as_radio(&self) -> Option<usize> {
+        if let Self::Radio(i) = self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
 }
 
 pub trait MenuCommand: Sized {
@@ -30,7 +42,7 @@ pub struct MenuBuilder<T> {
     menu: HMENU,
     index: u32,
     id: u32,
-    table: Vec<(std::mem::Discriminant<T>, std::mem::Discriminant<MenuItemType>)>,
+    table: Vec<(std::mem::Discriminant<T>, MenuItemType)>,
 }
 
 impl<T: MenuCommand> MenuBuilder<T> {
@@ -71,8 +83,7 @@ impl<T: MenuCommand> MenuBuilder<T> {
                 ..Default::default()
             };
             InsertMenuItemW(self.menu, self.index, false, &mut info);
-            self.table
-                .push((std::mem::discriminant(v), std::mem::discriminant(&MenuItemType::Item)));
+            self.table.push((std::mem::discriminant(v), MenuItemType::Item));
             self.index += 1;
             self.id += 1;
             self
@@ -93,16 +104,37 @@ impl<T: MenuCommand> MenuBuilder<T> {
                 ..Default::default()
             };
             InsertMenuItemW(self.menu, self.index, true, &mut info);
-            self.table.push((
-                std::mem::discriminant(v),
-                std::mem::discriminant(&MenuItemType::WithCheck(false)),
-            ));
+            self.table
+                .push((std::mem::discriminant(v), MenuItemType::WithCheck(checked)));
             self.index += 1;
             self.id += 1;
             self
         }
     }
 
+    /// Items of which only one is checked, told apart by `MenuItemType::Radio`.
+    pub fn radio_group(mut self, v: &T, texts: &[impl AsRef<str>], checked: usize) -> Self {
+        unsafe {
+            for (i, text) in texts.iter().enumerate() {
+                let name = to_wchar(text);
+                let mut info = MENUITEMINFOW {
+                    cbSize: std::mem::size_of::<MENUITEMINFOW>() as _,
+                    fMask: MIIM_TYPE | MIIM_ID | MIIM_STATE,
+                    fType: MFT_STRING | MFT_RADIOCHECK,
+                    dwTypeData: PWSTR(name.as_ptr() as _),
+                    wID: ROOT_ID + self.id,
+                    fState: if i == checked { MFS_CHECKED } else { MFS_UNCHECKED },
+                    ..Default::default()
+                };
+                InsertMenuItemW(self.menu, self.index, true, &mut info);
+                self.table.push((std::mem::discriminant(v), MenuItemType::Radio(i)));
+                self.index += 1;
+                self.id += 1;
+            }
+            self
+        }
+    }
+
     #[inline]
     pub fn separator(mut self) -> Self {
         unsafe {
@@ -123,6 +155,7 @@ impl<T: MenuCommand> MenuBuilder<T> {
         unsafe {
             DrawMenuBar(self.hwnd);
             Menu {
+                hwnd: self.hwnd,
                 menu: self.menu,
                 table: self.table,
             }
@@ -131,8 +164,9 @@ impl<T: MenuCommand> MenuBuilder<T> {
 }
 
 pub struct Menu<T> {
+    hwnd: HWND,
     menu: HMENU,
-    table: Vec<(std::mem::Discriminant<T>, std::mem::Discriminant<MenuItemType>)>,
+    table: Vec<(std::mem::Discriminant<T>, MenuItemType)>,
 }
 
 impl<T: MenuCommand> Menu<T> {
@@ -170,14 +204,26 @@ impl<T: MenuCommand> Menu<T> {
             }
             let id = id as u32;
             let (t, item_type) = self.table[id as usize];
-            let ret = if item_type == std::mem::discriminant(&MenuItemType::Item) {
-                T::from_command(t, MenuItemType::Item)
-            } else if item_type == std::mem::discriminant(&MenuItemType::WithCheck(false)) {
-                let b = !self.is_checked_item(id);
-                self.set_check_item(id, b);
-                T::from_command(t, MenuItemType::WithCheck(b))
-            } else {
-                return None;
+            let ret = match item_type {
+                MenuItemType::Item => T::from_command(t, MenuItemType::Item),
+                MenuItemType::WithCheck(_) => {
+                    let b = !self.is_checked_item(id);
+                    self.set_check_item(id, b);
+                    T::from_command(t, MenuItemType::WithCheck(b))
+                }
+                MenuItemType::Radio(i) => {
+                    let first = id - i as u32;
+                    let last = self.table[id as usize..]
+                        .iter()
+                        .take_while(|(v, item_type)| *v == t && item_type.as_radio().is_some())
+                        .count() as u32
+                        + id
+                        - 1;
+                    unsafe {
+                        CheckMenuRadioItem(self.menu, ROOT_ID + first, ROOT_ID + last, ROOT_ID + id, MF_BYCOMMAND.0);
+                    }
+                    T::from_command(t, MenuItemType::Radio(i))
+                }
             };
             Some(ret)
         } else {
@@ -186,9 +232,57 @@ impl<T: MenuCommand> Menu<T> {
     }
 }
 
+/// Reads the items of the menu bar of `hwnd` that run a command.
+pub fn read_menu_table(hwnd: HWND) -> MenuTable {
+    fn read(menu: HMENU, path: &mut Vec<String>, position: &mut Vec<u32>, table: &mut Vec<MenuEntry>) {
+        unsafe {
+            for i in 0..GetMenuItemCount(menu).max(0) {
+                let mut buffer = vec![0; 256];
+                let len = GetMenuStringW(
+                    menu,
+                    i as _,
+                    PWSTR(buffer.as_mut_ptr()),
+                    buffer.len() as _,
+                    MF_BYPOSITION,
+                );
+                path.push(menu_caption(&String::from_utf16_lossy(&buffer[..len.max(0) as usize])));
+                position.push(i as _);
+                let sub_menu = GetSubMenu(menu, i);
+                if !sub_menu.is_invalid() {
+                    read(sub_menu, path, position, table);
+                } else {
+                    // Separators have no command.
+                    let command = GetMenuItemID(menu, i);
+                    if command != 0 && command != u32::MAX {
+                        table.push(MenuEntry {
+                            path: path.clone(),
+                            position: position.clone(),
+                            command,
+                        });
+                    }
+                }
+                path.pop();
+                position.pop();
+            }
+        }
+    }
+
+    let mut table = vec![];
+    unsafe {
+        read(GetMenu(hwnd), &mut vec![], &mut vec![], &mut table);
+    }
+    MenuTable(table)
+}
+
 impl<T> Drop for Menu<T> {
     fn drop(&mut self) {
         unsafe {
+            let window_menu = GetMenu(self.hwnd);
+            let position = (0..GetMenuItemCount(window_menu)).find(|&i| GetSubMenu(window_menu, i) == self.menu);
+            if let Some(position) = position {
+                RemoveMenu(window_menu, position as _, MF_BYPOSITION);
+                DrawMenuBar(self.hwnd);
+            }
             DestroyMenu(self.menu);
         }
     }
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MidiMessage {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+}
+
+impl MidiMessage {
+    /// Decodes a short message packed as the `dwParam1` of `MIM_DATA`.
+    pub fn from_short_message(v: u32) -> Option<Self> {
+        let bytes = [v as u8, (v >> 8) as u8, (v >> 16) as u8];
+        MidiParser::new().parse(&bytes).into_iter().next()
+    }
+}
+
+#[inline]
+fn data_len(status: u8) -> usize {
+    match status {
+        0x80..=0xbf | 0xe0..=0xef | 0xf2 => 2,
+        0xc0..=0xdf | 0xf1 | 0xf3 => 1,
+        _ => 0,
+    }
+}
+
+/// Decodes a MIDI byte stream including running status, skipping system exclusive and real-time messages.
+#[derive(Debug, Default)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: [u8; 2],
+    len: usize,
+}
+
+impl MidiParser {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            0xf8..=0xff => return None,
+            0x80..=0xf7 => {
+                self.status = (data_len(byte) > 0).then_some(byte);
+                self.len = 0;
+                return None;
+            }
+            _ => {}
+        }
+        let status = self.status?;
+        self.data[self.len] = byte;
+        self.len += 1;
+        if self.len < data_len(status) {
+            return None;
+        }
+        self.len = 0;
+        if status >= 0xf0 {
+            self.status = None;
+            return None;
+        }
+        let channel = status & 0x0f;
+        let [d0, d1] = self.data;
+        match status & 0xf0 {
+            0x90 if d1 > 0 => Some(MidiMessage::NoteOn {
+                channel,
+                note: d0,
+                velocity: d1,
+            }),
+            0x80 | 0x90 => Some(MidiMessage::NoteOff { channel, note: d0 }),
+            0xb0 => Some(MidiMessage::ControlChange {
+                channel,
+                controller: d0,
+                value: d1,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        bytes.iter().filter_map(|&b| self.push(b)).collect()
+    }
+}
+
+/// How a relative knob encodes the amount it was turned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelativeEncoding {
+    /// 1 to 63 clockwise, 127 down to 65 counterclockwise.
+    #[default]
+    TwosComplement,
+    /// 64 is the center.
+    BinaryOffset,
+    /// Bit 6 is the sign.
+    SignMagnitude,
+}
+
+impl RelativeEncoding {
+    pub fn delta(&self, value: u8) -> i32 {
+        let value = (value & 0x7f) as i32;
+        match self {
+            Self::TwosComplement if value >= 0x40 => value - 0x80,
+            Self::TwosComplement => value,
+            Self::BinaryOffset => value - 0x40,
+            Self::SignMagnitude if value & 0x40 != 0 => -(value & 0x3f),
+            Self::SignMagnitude => value,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct NoteBinding {
+    /// 1 to 16. Any channel when omitted.
+    #[serde(default)]
+    pub channel: Option<u8>,
+    pub note: u8,
+    pub action: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub enum ControlAction {
+    Relative {
+        prev: String,
+        next: String,
+        #[serde(default)]
+        encoding: RelativeEncoding,
+    },
+    Absolute {
+        action: String,
+    },
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ControlBinding {
+    /// 1 to 16. Any channel when omitted.
+    #[serde(default)]
+    pub channel: Option<u8>,
+    pub controller: u8,
+    #[serde(flatten)]
+    pub action: ControlAction,
+}
+
+/// The contents of midi_map.json.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct MidiMap {
+    /// Part of the input device name. The first device when omitted.
+    #[serde(default)]
+    pub device: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<NoteBinding>,
+    #[serde(default)]
+    pub controls: Vec<ControlBinding>,
+}
+
+impl MidiMap {
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// All mmd_map IDs used by the bindings.
+    pub fn action_ids(&self) -> impl Iterator<Item = &str> {
+        let notes = self.notes.iter().map(|n| vec![n.action.as_str()]);
+        let controls = self.controls.iter().map(|c| match &c.action {
+            ControlAction::Relative { prev, next, .. } => vec![prev.as_str(), next.as_str()],
+            ControlAction::Absolute { action } => vec![action.as_str()],
+        });
+        notes.chain(controls).flatten()
+    }
+}
+
+#[inline]
+fn match_channel(binding: Option<u8>, channel: u8) -> bool {
+    match binding {
+        Some(c) => c == channel + 1,
+        None => true,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MidiAction<'a> {
+    /// Held until the matching `Release`, like a key.
+    Press(&'a str),
+    Release(&'a str),
+    /// Runs once without a release, one step per message whatever the knob's speed.
+    Trigger(&'a str),
+}
+
+impl<'a> MidiAction<'a> {
+    #[inline]
+    pub fn id(&self) -> &'a str {
+        match self {
+            Self::Press(id) | Self::Release(id) | Self::Trigger(id) => id,
+        }
+    }
+}
+
+/// Turns MIDI messages into mmd_map IDs.
+#[derive(Debug)]
+pub struct MidiMapper {
+    map: MidiMap,
+    controls: HashMap<(u8, u8), bool>,
+}
+
+impl MidiMapper {
+    /// Values from this on turn an absolute controller on, e.g. a sustain pedal or a toggle button.
+    const THRESHOLD: u8 = 64;
+
+    #[inline]
+    pub fn new(map: MidiMap) -> Self {
+        Self {
+            map,
+            controls: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn midi_map(&self) -> &MidiMap {
+        &self.map
+    }
+
+    pub fn map(&mut self, msg: &MidiMessage) -> Vec<MidiAction> {
+        match *msg {
+            MidiMessage::NoteOn { channel, note, .. } => self
+                .map
+                .notes
+                .iter()
+                .filter(|n| n.note == note && match_channel(n.channel, channel))
+                .map(|n| MidiAction::Press(&n.action))
+                .collect(),
+            MidiMessage::NoteOff { channel, note } => self
+                .map
+                .notes
+                .iter()
+                .filter(|n| n.note == note && match_channel(n.channel, channel))
+                .map(|n| MidiAction::Release(&n.action))
+                .collect(),
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => {
+                let on = value >= Self::THRESHOLD;
+                let prev_on = self.controls.insert((channel, controller), on).unwrap_or(false);
+                self.map
+                    .controls
+                    .iter()
+                    .filter(|c| c.controller == controller && match_channel(c.channel, channel))
+                    .filter_map(|c| match &c.action {
+                        ControlAction::Relative { prev, next, encoding } => match encoding.delta(value) {
+                            d if d < 0 => Some(MidiAction::Trigger(prev)),
+                            d if d > 0 => Some(MidiAction::Trigger(next)),
+                            _ => None,
+                        },
+                        ControlAction::Absolute { action } if on && !prev_on => Some(MidiAction::Press(action)),
+                        ControlAction::Absolute { action } if !on && prev_on => Some(MidiAction::Release(action)),
+                        ControlAction::Absolute { .. } => None,
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_midi_test() {
+        let mut parser = MidiParser::new();
+        let bytes = [
+            0x90, 0x24, 0x64, // note on
+            0x25, 0x00, // running status, note on with velocity 0
+            0xf8, // real-time clock in between
+            0xb1, 0x10, 0xf8, 0x41, // control change
+            0xf0, 0x7e, 0x7f, 0xf7, // system exclusive
+            0x10, 0x01, // data without status
+            0xc0, 0x05, // program change
+            0x81, 0x24, 0x40, // note off
+        ];
+        let msgs = parser.parse(&bytes);
+        assert!(
+            msgs == [
+                MidiMessage::NoteOn {
+                    channel: 0,
+                    note: 0x24,
+                    velocity: 0x64
+                },
+                MidiMessage::NoteOff { channel: 0, note: 0x25 },
+                MidiMessage::ControlChange {
+                    channel: 1,
+                    controller: 0x10,
+                    value: 0x41
+                },
+                MidiMessage::NoteOff { channel: 1, note: 0x24 },
+            ]
+        );
+        assert!(
+            MidiMessage::from_short_message(0x7f0bb2)
+                == Some(MidiMessage::ControlChange {
+                    channel: 2,
+                    controller: 0x0b,
+                    value: 0x7f
+                })
+        );
+    }
+
+    #[test]
+    fn relative_encoding_test() {
+        assert!(RelativeEncoding::TwosComplement.delta(0x01) == 1);
+        assert!(RelativeEncoding::TwosComplement.delta(0x7f) == -1);
+        assert!(RelativeEncoding::BinaryOffset.delta(0x3f) == -1);
+        assert!(RelativeEncoding::BinaryOffset.delta(0x42) == 2);
+        assert!(RelativeEncoding::SignMagnitude.delta(0x41) == -1);
+        assert!(RelativeEncoding::SignMagnitude.delta(0x03) == 3);
+    }
+
+    #[test]
+    fn midi_mapper_test() {
+        let map: MidiMap = serde_json::from_str(
+            r#"{
+                "notes": [
+                    { "note": 36, "action": "Undo" },
+                    { "channel": 2, "note": 37, "action": "Redo" }
+                ],
+                "controls": [
+                    { "controller": 16, "prev": "FramePrev", "next": "FrameNext" },
+                    { "controller": 17, "prev": "ModelSelectorPrev", "next": "ModelSelectorNext", "encoding": "binary_offset" },
+                    { "controller": 64, "action": "Play" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(map.action_ids().count() == 7);
+        let mut mapper = MidiMapper::new(map);
+        let mut parser = MidiParser::new();
+        let mut run = |bytes: &[u8]| {
+            parser
+                .parse(bytes)
+                .iter()
+                .flat_map(|msg| {
+                    mapper
+                        .map(msg)
+                        .into_iter()
+                        .map(|a| format!("{:?}", a))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(run(&[0x95, 36, 100, 36, 0]) == ["Press(\"Undo\")", "Release(\"Undo\")"]);
+        assert!(run(&[0x90, 37, 100]).is_empty());
+        assert!(run(&[0x91, 37, 100]) == ["Press(\"Redo\")"]);
+        assert!(run(&[0xb0, 16, 0x7e, 16, 0x02]) == ["Trigger(\"FramePrev\")", "Trigger(\"FrameNext\")"]);
+        assert!(run(&[0xb0, 17, 0x40, 17, 0x3f]) == ["Trigger(\"ModelSelectorPrev\")"]);
+        assert!(run(&[0xb0, 64, 0x7f, 64, 0x7f, 64, 0x00]) == ["Press(\"Play\")", "Release(\"Play\")"]);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::*;
+use windows::Win32::Media::{Audio::*, MM_MIM_DATA};
+
+/// Posted to the MMD window with a short MIDI message in `WPARAM`.
+pub const WM_MMACCEL_MIDI: u32 = WM_APP + 1;
+
+unsafe extern "system" fn midi_in_proc(_: HMIDIIN, msg: u32, instance: usize, param1: usize, _: usize) {
+    if msg == MM_MIM_DATA {
+        PostMessageW(HWND(instance as _), WM_MMACCEL_MIDI, WPARAM(param1), LPARAM(0));
+    }
+}
+
+fn device_name(id: u32) -> Option<String> {
+    unsafe {
+        let mut caps = MIDIINCAPSW::default();
+        let ret = midiInGetDevCapsW(id as _, &mut caps, std::mem::size_of::<MIDIINCAPSW>() as _);
+        if ret != 0 {
+            return None;
+        }
+        let len = caps.szPname.iter().position(|&c| c == 0).unwrap_or(caps.szPname.len());
+        Some(String::from_utf16_lossy(&caps.szPname[..len]))
+    }
+}
+
+pub struct MidiInput(HMIDIIN);
+
+impl MidiInput {
+    /// Opens the first device whose name contains `name`, or the first device when `name` is `None`.
+    pub fn open(name: Option<&str>, hwnd: HWND) -> Option<Self> {
+        unsafe {
+            let (id, device) = (0..midiInGetNumDevs())
+                .filter_map(|id| device_name(id).map(|device| (id, device)))
+                .find(|(_, device)| match name {
+                    Some(name) => device.contains(name),
+                    None => true,
+                })?;
+            let mut handle = HMIDIIN::default();
+            let ret = midiInOpen(&mut handle, id, midi_in_proc as usize, hwnd.0 as _, CALLBACK_FUNCTION);
+            if ret != 0 {
+                log::error!("midiInOpen: {} ({})", device, ret);
+                return None;
+            }
+            midiInStart(handle);
+            log::debug!("open MIDI device: {}", device);
+            Some(Self(handle))
+        }
+    }
+}
+
+impl Drop for MidiInput {
+    fn drop(&mut self) {
+        unsafe {
+            midiInStop(self.0);
+            midiInReset(self.0);
+            midiInClose(self.0);
+        }
+    }
+}
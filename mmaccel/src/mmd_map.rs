@@ -1,45 +1,155 @@
+use key_map::{match_pattern, merge_overlays, Overlay, OverlayError};
+use serde::de::{Error as _, SeqAccess};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::BufReader;
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum ComboDir {
     Prev,
     Next,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// A number in an item. It is compared by the bits so that `ItemKind` can be a key of a map.
+#[derive(Clone, Copy, Debug)]
+pub struct Number(pub f64);
+
+impl PartialEq for Number {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Number {}
+
+impl std::hash::Hash for Number {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Number {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let v = f64::deserialize(deserializer)?;
+        if !v.is_finite() {
+            return Err(D::Error::custom(format!("invalid number {}", v)));
+        }
+        Ok(Self(v))
+    }
+}
+
+/// How a numeric edit control is changed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EditOp {
+    Set(Number),
+    Add(Number),
+    Mul(Number),
+}
+
+/// The most decimal places taken from the number of an item, such as `0.001` for a fine step.
+const MAX_ITEM_PLACES: usize = 4;
+
+#[inline]
+fn decimal_places(text: &str) -> usize {
+    text.split_once('.').map_or(0, |(_, fraction)| fraction.len())
+}
+
+impl EditOp {
+    /// The new text from the current text of the control, in the decimal places of the control.
+    /// A value or a step finer than the control adds its places up to `MAX_ITEM_PLACES`,
+    /// while a factor says nothing about the precision.
+    /// `None` if the current text is not a number.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let text = text.trim();
+        let (v, n) = match self {
+            Self::Set(value) => (value.0, Some(value.0)),
+            Self::Add(step) => (text.parse::<f64>().ok()? + step.0, Some(step.0)),
+            Self::Mul(factor) => (text.parse::<f64>().ok()? * factor.0, None),
+        };
+        let item_places = n.map_or(0, |n| decimal_places(&n.to_string()).min(MAX_ITEM_PLACES));
+        let places = decimal_places(text).max(item_places);
+        let s = format!("{:.*}", places, v);
+        // "-0.000" is shown as "0.000".
+        if s.starts_with('-') && s[1..].chars().all(|c| c == '0' || c == '.') {
+            Some(s[1..].to_string())
+        } else {
+            Some(s)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum ItemKind {
     Key(u32),
     Button(u32),
     Edit(u32),
+    EditValue(u32, EditOp),
     Combo(ComboDir, u32),
+    /// The positions of the menu and the item in the menu bar when MMAccel builds its menu.
     Menu(u32, u32),
+    /// The command ID of a menu item.
+    MenuCommand(u32),
+    /// An index of the caption paths in `MmdMap`.
+    MenuPath(usize),
     Fold(u32, u32),
     KillFocus,
     FoldAll,
     UnfoldAll,
+    /// An index of the macros in macros.json.
+    Macro(usize),
+    /// An index of the actions in key_map.json.
+    Custom(usize),
 }
 
 impl ItemKind {
-    fn new(a: &[serde_json::Value]) -> Option<Self> {
-        let kind = match a[1].as_str()? {
-            "key" if a.len() == 3 => Self::Key(u32::from_str_radix(a[2].as_str()?, 16).ok()?),
-            "button" if a.len() == 3 => Self::Button(u32::from_str_radix(a[2].as_str()?, 16).ok()?),
-            "edit" if a.len() == 3 => Self::Edit(u32::from_str_radix(a[2].as_str()?, 16).ok()?),
-            "combo_prev" if a.len() == 3 => Self::Combo(ComboDir::Prev, u32::from_str_radix(a[2].as_str()?, 16).ok()?),
-            "combo_next" if a.len() == 3 => Self::Combo(ComboDir::Next, u32::from_str_radix(a[2].as_str()?, 16).ok()?),
-            "menu" if a.len() == 4 => Self::Menu(a[2].as_u64()? as _, a[3].as_u64()? as _),
-            "fold" if a.len() == 4 => Self::Fold(
-                u32::from_str_radix(a[2].as_str()?, 16).ok()?,
-                u32::from_str_radix(a[3].as_str()?, 16).ok()?,
-            ),
+    const KINDS: &'static [&'static str] = &[
+        "key",
+        "button",
+        "edit",
+        "edit_set",
+        "edit_add",
+        "edit_mul",
+        "combo_prev",
+        "combo_next",
+        "menu",
+        "menu_command",
+        "menu_path",
+        "fold",
+        "kill_focus",
+        "fold_all",
+        "unfold_all",
+    ];
+
+    /// Reads the arguments following `kind` in an item.
+    fn from_seq<'de, A>(kind: &str, seq: &mut A) -> Result<KindDef, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let kind = match kind {
+            "key" => Self::Key(element::<HexId, _>(seq, "key")?.0),
+            "button" => Self::Button(element::<HexId, _>(seq, "id")?.0),
+            "edit" => Self::Edit(element::<HexId, _>(seq, "id")?.0),
+            "edit_set" => Self::EditValue(element::<HexId, _>(seq, "id")?.0, EditOp::Set(element(seq, "value")?)),
+            "edit_add" => Self::EditValue(element::<HexId, _>(seq, "id")?.0, EditOp::Add(element(seq, "step")?)),
+            "edit_mul" => Self::EditValue(element::<HexId, _>(seq, "id")?.0, EditOp::Mul(element(seq, "factor")?)),
+            "combo_prev" => Self::Combo(ComboDir::Prev, element::<HexId, _>(seq, "id")?.0),
+            "combo_next" => Self::Combo(ComboDir::Next, element::<HexId, _>(seq, "id")?.0),
+            "menu" => Self::Menu(element(seq, "menu")?, element(seq, "item")?),
+            "menu_command" => Self::MenuCommand(element(seq, "command")?),
+            "menu_path" => return Ok(KindDef::MenuPath(element(seq, "path")?)),
+            "fold" => Self::Fold(element::<HexId, _>(seq, "hide")?.0, element::<HexId, _>(seq, "show")?.0),
             "kill_focus" => Self::KillFocus,
             "fold_all" => Self::FoldAll,
             "unfold_all" => Self::UnfoldAll,
-            _ => return None,
+            _ => return Err(A::Error::unknown_variant(kind, Self::KINDS)),
         };
-        Some(kind)
+        Ok(KindDef::Kind(kind))
     }
 
     #[inline]
@@ -50,6 +160,15 @@ impl ItemKind {
         }
     }
 
+    /// The control that runs the item.
+    #[inline]
+    pub fn control_id(&self) -> Option<u32> {
+        match self {
+            Self::Button(id) | Self::Edit(id) | Self::EditValue(id, _) | Self::Combo(_, id) => Some(*id),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn as_fold(&self) -> Option<(u32, u32)> {
         match self {
@@ -59,60 +178,345 @@ impl ItemKind {
     }
 }
 
+/// A control ID or a virtual key code written in hex such as `"190"`.
+struct HexId(u32);
+
+impl<'de> serde::Deserialize<'de> for HexId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        u32::from_str_radix(&s, 16)
+            .map(HexId)
+            .map_err(|_| D::Error::custom(format!("invalid hex \"{}\"", s)))
+    }
+}
+
+/// Reads the next element named `field` in the error.
+fn element<'de, T, A>(seq: &mut A, field: &str) -> Result<T, A::Error>
+where
+    T: serde::Deserialize<'de>,
+    A: SeqAccess<'de>,
+{
+    seq.next_element::<T>()
+        .map_err(|e| A::Error::custom(format!("{}: {}", field, e)))?
+        .ok_or_else(|| A::Error::custom(format!("{}: missing", field)))
+}
+
+/// The kind of an item as written in mmd_map.json.
+#[derive(Clone, PartialEq, Debug)]
+enum KindDef {
+    Kind(ItemKind),
+    /// The caption path of `ItemKind::MenuPath`, which is kept in `MmdMap` because `ItemKind` is `Copy`.
+    MenuPath(String),
+}
+
+/// An item is written as `[name, kind, args...]`.
+#[derive(Clone, Debug)]
+struct ItemDef {
+    name: String,
+    kind: KindDef,
+}
+
 #[derive(Clone, Debug)]
 pub struct Item {
     pub name: String,
     pub kind: ItemKind,
 }
 
-impl Item {
-    fn new(a: &[serde_json::Value]) -> Option<Self> {
-        if a.len() < 2 {
-            return None;
+impl<'de> serde::Deserialize<'de> for ItemDef {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = ItemDef;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(formatter, "an array of the name, the kind and the arguments")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let name: String = element(&mut seq, "name")?;
+                let kind: String = element(&mut seq, "kind")?;
+                let kind = ItemKind::from_seq(&kind, &mut seq).map_err(|e| A::Error::custom(format!("kind: {}", e)))?;
+                if seq.next_element::<Value>()?.is_some() {
+                    return Err(A::Error::custom("too many elements"));
+                }
+                Ok(ItemDef { name, kind })
+            }
         }
-        Some(Self {
-            name: a[0].as_str()?.to_string(),
-            kind: ItemKind::new(a)?,
-        })
+
+        deserializer.deserialize_seq(Visitor)
     }
 }
 
+/// A malformed entry of mmd_map.json.
 #[derive(Debug)]
-pub struct MmdMap(Vec<(String, Item)>);
+pub struct EntryError {
+    pub category: String,
+    /// `None` if the category itself is malformed.
+    pub id: Option<String>,
+    pub message: String,
+}
 
-impl MmdMap {
-    pub fn from_file(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
-        fn items(m: &mut Vec<(String, Item)>, v: &Value) -> Option<()> {
-            match v {
-                Value::Object(obj) => {
-                    for (key, value) in obj.iter() {
-                        if value.is_object() {
-                            items(m, value)?;
-                        } else if let Some(a) = value.as_array() {
-                            m.push((key.clone(), Item::new(a)?));
-                        }
-                    }
-                    Some(())
-                }
-                _ => None,
+impl std::fmt::Display for EntryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.id {
+            Some(id) => write!(f, "{}: {}: {}", self.category, id, self.message),
+            None => write!(f, "{}: {}", self.category, self.message),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {}
+
+/// The version resource of MikuMikuDance.exe that mmd_map.json is made for, v9.32 x64.
+pub const SUPPORTED_VERSION: MmdVersion = MmdVersion([9, 3, 2, 0]);
+
+/// The file version of MikuMikuDance.exe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MmdVersion(pub [u16; 4]);
+
+impl MmdVersion {
+    /// `ms` and `ls` are `dwFileVersionMS` and `dwFileVersionLS` of `VS_FIXEDFILEINFO`.
+    #[inline]
+    pub fn from_ms_ls(ms: u32, ls: u32) -> Self {
+        Self([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16])
+    }
+}
+
+impl std::fmt::Display for MmdVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// Chooses one of the mmd_map files for the running MMD.
+#[derive(Clone, Copy, Debug)]
+pub struct Variant {
+    /// `None` if the version resource could not be read.
+    pub version: Option<MmdVersion>,
+    /// The menus differ in the English mode of MMD.
+    pub english: bool,
+}
+
+/// The mmd_map file chosen by `Variant::select`.
+#[derive(Clone, Debug)]
+pub struct Selected {
+    pub path: std::path::PathBuf,
+    /// `false` if no file is made for the version, so the IDs may not match.
+    pub is_known: bool,
+}
+
+impl Variant {
+    /// The file names to look for, the most specific first.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut v = vec![];
+        if let Some(version) = self.version {
+            if self.english {
+                v.push(format!("mmd_map.{}.en.json", version));
             }
+            v.push(format!("mmd_map.{}.json", version));
         }
+        if self.english {
+            v.push("mmd_map.en.json".to_string());
+        }
+        v.push("mmd_map.json".to_string());
+        v
+    }
+
+    /// The first file that exists in `dir`, or mmd_map.json.
+    pub fn select(&self, dir: impl AsRef<std::path::Path>) -> Selected {
+        let dir = dir.as_ref();
+        let names = self.file_names();
+        let (index, path) = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (i, dir.join(name)))
+            .find(|(_, path)| path.is_file())
+            .unwrap_or_else(|| (names.len() - 1, dir.join(names.last().unwrap())));
+        let versioned = self.version.is_some() && index < if self.english { 2 } else { 1 };
+        Selected {
+            path,
+            is_known: versioned || self.version == Some(SUPPORTED_VERSION),
+        }
+    }
+}
+
+/// The caption without the access key such as "(&F)", the `&` and the shortcut after a tab.
+pub fn menu_caption(text: &str) -> String {
+    let mut text = text.split('\t').next().unwrap_or_default().to_string();
+    if let Some(i) = text.find("(&") {
+        let mut rest = text[i + 2..].chars();
+        if let (Some(key), Some(')')) = (rest.next(), rest.next()) {
+            text.replace_range(i..i + 3 + key.len_utf8(), "");
+        }
+    }
+    text.replace('&', "").trim().to_string()
+}
 
+/// A menu item that runs a command.
+#[derive(Clone, Debug)]
+pub struct MenuEntry {
+    /// The captions from the menu bar, made by `menu_caption`.
+    pub path: Vec<String>,
+    /// The positions from the menu bar.
+    pub position: Vec<u32>,
+    pub command: u32,
+}
+
+/// The menu items of MMD read when the menu is built, so that the items can be found after
+/// MME or another plugin inserts its menu.
+#[derive(Clone, Debug, Default)]
+pub struct MenuTable(pub Vec<MenuEntry>);
+
+impl MenuTable {
+    #[inline]
+    pub fn by_position(&self, index: u32, sub_index: u32) -> Option<u32> {
+        self.0
+            .iter()
+            .find(|entry| entry.position == [index, sub_index])
+            .map(|entry| entry.command)
+    }
+
+    /// `path` is the captions joined with `/`, where each caption can have `*` and `?`.
+    pub fn by_path(&self, path: &str) -> Option<u32> {
+        let path = path.split('/').map(|s| s.trim()).collect::<Vec<_>>();
+        self.0
+            .iter()
+            .find(|entry| {
+                entry.path.len() == path.len()
+                    && entry
+                        .path
+                        .iter()
+                        .zip(path.iter())
+                        .all(|(caption, pattern)| match_pattern(pattern, caption))
+            })
+            .map(|entry| entry.command)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MmdMap {
+    items: Vec<(String, Item)>,
+    /// The caption paths of `ItemKind::MenuPath`.
+    menu_paths: Vec<String>,
+    /// The command IDs of `ItemKind::Menu` and `ItemKind::MenuPath` found by `resolve_menus`.
+    menu_commands: HashMap<ItemKind, u32>,
+}
+
+impl MmdMap {
+    /// Loads the valid items and returns the malformed entries along with them.
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<(Self, Vec<EntryError>)> {
+        Self::load_with_overlays(path, &[]).map(|(m, errors, _)| (m, errors))
+    }
+
+    /// Loads the items with `overlays` merged, and returns the conflicts of the overlays too.
+    pub fn load_with_overlays(
+        path: impl AsRef<std::path::Path>,
+        overlays: &[Overlay],
+    ) -> std::io::Result<(Self, Vec<EntryError>, Vec<OverlayError>)> {
         let file = File::open(path)?;
-        let data: Value = serde_json::from_reader(BufReader::new(file))?;
+        let mut data: serde_json::Map<String, Value> = serde_json::from_reader(BufReader::new(file))?;
+        let conflicts = merge_overlays(&mut data, overlays);
+        let (m, errors) = Self::from_categories(data.into_iter().collect());
+        Ok((m, errors, conflicts))
+    }
+
+    fn from_categories(data: BTreeMap<String, Value>) -> (Self, Vec<EntryError>) {
         let mut m = Vec::new();
-        items(&mut m, &data).ok_or(std::io::ErrorKind::InvalidData)?;
-        Ok(Self(m))
+        let mut menu_paths = Vec::new();
+        let mut errors = Vec::new();
+        for (category, items) in data {
+            let items = match serde_json::from_value::<BTreeMap<String, Value>>(items) {
+                Ok(items) => items,
+                Err(e) => {
+                    errors.push(EntryError {
+                        category,
+                        id: None,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            for (id, item) in items {
+                match serde_json::from_value::<ItemDef>(item) {
+                    Ok(ItemDef { name, kind }) => {
+                        let kind = match kind {
+                            KindDef::Kind(kind) => kind,
+                            KindDef::MenuPath(path) => {
+                                menu_paths.push(path);
+                                ItemKind::MenuPath(menu_paths.len() - 1)
+                            }
+                        };
+                        m.push((id, Item { name, kind }));
+                    }
+                    Err(e) => errors.push(EntryError {
+                        category: category.clone(),
+                        id: Some(id),
+                        message: e.to_string(),
+                    }),
+                }
+            }
+        }
+        (
+            Self {
+                items: m,
+                menu_paths,
+                menu_commands: HashMap::new(),
+            },
+            errors,
+        )
     }
 
     #[inline]
     pub fn get(&self, key: impl AsRef<str>) -> Option<&Item> {
-        self.0.iter().find(|(k, _)| k == key.as_ref()).map(|(_, item)| item)
+        self.items.iter().find(|(k, _)| k == key.as_ref()).map(|(_, item)| item)
+    }
+
+    #[inline]
+    pub fn push(&mut self, id: impl Into<String>, item: Item) {
+        self.items.push((id.into(), item));
     }
 
     #[inline]
     pub fn iter(&self) -> std::slice::Iter<(String, Item)> {
-        self.0.iter()
+        self.items.iter()
+    }
+
+    /// Looks up the command IDs of the menu items in `table`, and returns the IDs not found.
+    /// The items by position that are not found stay by position.
+    pub fn resolve_menus(&mut self, table: &MenuTable) -> Vec<String> {
+        self.menu_commands.clear();
+        let mut not_found = vec![];
+        for (id, item) in self.items.iter() {
+            let command = match item.kind {
+                ItemKind::Menu(index, sub_index) => table.by_position(index, sub_index),
+                ItemKind::MenuPath(path) => table.by_path(&self.menu_paths[path]),
+                _ => continue,
+            };
+            match command {
+                Some(command) => {
+                    self.menu_commands.insert(item.kind, command);
+                }
+                None => not_found.push(id.clone()),
+            }
+        }
+        not_found
+    }
+
+    /// The command IDs found by `resolve_menus`.
+    #[inline]
+    pub fn menu_commands(&self) -> &HashMap<ItemKind, u32> {
+        &self.menu_commands
     }
 }
 
@@ -122,7 +526,8 @@ mod tests {
 
     #[test]
     fn load_mmd_map() {
-        let m = MmdMap::from_file("src/mmd_map.json").unwrap();
+        let (m, errors) = MmdMap::load("src/mmd_map.json").unwrap();
+        assert!(errors.is_empty());
         let item = &m.iter().find(|(key, _)| key == "Undo").unwrap().1;
         assert!(item.name == "元に戻す");
         assert!(matches!(item.kind, ItemKind::Button(0x190)));
@@ -130,4 +535,138 @@ mod tests {
         assert!(item.name == "バージョン情報");
         assert!(matches!(item.kind, ItemKind::Menu(7, 6)));
     }
+
+    #[test]
+    fn entry_error_test() {
+        let data = serde_json::from_str(
+            r#"{
+                "a": {
+                    "Undo": [ "元に戻す", "button", "190" ],
+                    "Bad": [ "hoge", "button", "xyz" ],
+                    "Fold": [ "hoge", "fold", "1" ],
+                    "Kind": [ "hoge", "foo" ],
+                    "Long": [ "hoge", "kill_focus", "1" ]
+                },
+                "b": [ 1 ]
+            }"#,
+        )
+        .unwrap();
+        let (m, errors) = MmdMap::from_categories(data);
+        assert!(m.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>() == ["Undo"]);
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert!(errors.len() == 5);
+        assert!(errors[0].starts_with("a: Bad: kind: id: invalid hex \"xyz\""));
+        assert!(errors[1].starts_with("a: Fold: kind: show: missing"));
+        assert!(errors[2].starts_with("a: Kind: kind: unknown variant `foo`"));
+        assert!(errors[3].starts_with("a: Long: too many elements"));
+        assert!(errors[4].starts_with("b: "));
+    }
+
+    #[test]
+    fn resolve_menus_test() {
+        let data = serde_json::from_str(
+            r#"{
+                "a": {
+                    "Open": [ "開く", "menu", 0, 1 ],
+                    "Open2": [ "開く", "menu_path", "ファイル/開く" ],
+                    "About": [ "バージョン情報", "menu_path", "ヘルプ/バージョン*" ],
+                    "Command": [ "hoge", "menu_command", 40001 ],
+                    "Missing": [ "hoge", "menu_path", "ファイル/hoge" ],
+                    "Bad": [ "hoge", "menu_path", 1 ],
+                    "Long": [ "hoge", "menu_path", "ファイル/開く", 1 ]
+                }
+            }"#,
+        )
+        .unwrap();
+        let (mut m, errors) = MmdMap::from_categories(data);
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        assert!(errors.len() == 2);
+        assert!(errors[0].starts_with("a: Bad: kind: path: "));
+        assert!(errors[1] == "a: Long: too many elements");
+        assert!(m.get("Command").unwrap().kind == ItemKind::MenuCommand(40001));
+        assert!(menu_caption("開く(&O)...\tCtrl+O") == "開く...");
+        assert!(menu_caption("ファイル(&F)") == "ファイル");
+        assert!(menu_caption("Save &As") == "Save As");
+        let entry = |path: &[&str], position: &[u32], command| MenuEntry {
+            path: path.iter().map(|s| menu_caption(s)).collect(),
+            position: position.to_vec(),
+            command,
+        };
+        let table = MenuTable(vec![
+            entry(&["ファイル(&F)", "新規(&N)"], &[0, 0], 100),
+            entry(&["ファイル(&F)", "開く(&O)\tCtrl+O"], &[0, 1], 101),
+            entry(&["ヘルプ(&H)", "バージョン情報(&A)"], &[7, 6], 700),
+        ]);
+        let not_found = m.resolve_menus(&table);
+        assert!(not_found == ["Missing"]);
+        let command = |id: &str| m.menu_commands().get(&m.get(id).unwrap().kind).copied();
+        assert!(command("Open") == Some(101));
+        assert!(command("Open2") == Some(101));
+        assert!(command("About") == Some(700));
+        assert!(command("Missing").is_none());
+    }
+
+    #[test]
+    fn edit_value_test() {
+        let item: ItemDef = serde_json::from_str(r#"[ "位置X +0.1", "edit_add", "220", 0.1 ]"#).unwrap();
+        assert!(item.kind == KindDef::Kind(ItemKind::EditValue(0x220, EditOp::Add(Number(0.1)))));
+        assert!(serde_json::from_str::<ItemDef>(r#"[ "hoge", "edit_set", "220" ]"#).is_err());
+        assert!(serde_json::from_str::<ItemDef>(r#"[ "hoge", "edit_mul", "220", "2" ]"#).is_err());
+        let add = |step: f64, text: &str| EditOp::Add(Number(step)).apply(text);
+        assert!(add(0.1, "1.250").unwrap() == "1.350");
+        assert!(add(-0.1, " 0.100 ").unwrap() == "0.000");
+        assert!(add(-1.0, "3").unwrap() == "2");
+        assert!(add(0.1, "").is_none());
+        assert!(EditOp::Mul(Number(1.1)).apply("10.00").unwrap() == "11.00");
+        // A step finer than the control is not rounded away.
+        assert!(add(0.1, "3").unwrap() == "3.1");
+        assert!(add(0.05, "1.0").unwrap() == "1.05");
+        assert!(EditOp::Mul(Number(1.1)).apply("10").unwrap() == "11");
+        assert!(EditOp::Mul(Number(1.0 / 1.1)).apply("1.000").unwrap() == "0.909");
+        assert!(add(1.0 / 3.0, "1").unwrap() == "1.3333");
+        assert!(EditOp::Set(Number(0.25)).apply("1.0").unwrap() == "0.25");
+        assert!(EditOp::Set(Number(1.0)).apply("0.500").unwrap() == "1.000");
+        assert!(EditOp::Set(Number(1.0)).apply("hoge").unwrap() == "1");
+    }
+
+    #[test]
+    fn variant_test() {
+        let version = MmdVersion::from_ms_ls(0x0009_0003, 0x0001_0000);
+        assert!(version.to_string() == "9.3.1.0");
+        let variant = Variant {
+            version: Some(version),
+            english: true,
+        };
+        assert!(
+            variant.file_names()
+                == [
+                    "mmd_map.9.3.1.0.en.json",
+                    "mmd_map.9.3.1.0.json",
+                    "mmd_map.en.json",
+                    "mmd_map.json"
+                ]
+        );
+        let dir = std::env::temp_dir().join(format!("mmaccel_variant_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("mmd_map.json"), "{}").unwrap();
+        let selected = variant.select(&dir);
+        assert!(selected.path == dir.join("mmd_map.json") && !selected.is_known);
+        std::fs::write(dir.join("mmd_map.9.3.1.0.json"), "{}").unwrap();
+        let selected = variant.select(&dir);
+        assert!(selected.path == dir.join("mmd_map.9.3.1.0.json") && selected.is_known);
+        let supported = Variant {
+            version: Some(SUPPORTED_VERSION),
+            english: false,
+        };
+        assert!(supported.select(&dir).is_known);
+        assert!(
+            !Variant {
+                version: None,
+                english: false
+            }
+            .select(&dir)
+            .is_known
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
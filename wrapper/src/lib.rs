@@ -1,6 +1,6 @@
 use windows::Win32::{
-    Foundation::*, System::LibraryLoader::*, System::Threading::*, UI::Input::KeyboardAndMouse::*, UI::Shell::*,
-    UI::WindowsAndMessaging::*,
+    Foundation::*, Globalization::*, System::LibraryLoader::*, System::Threading::*, UI::Input::KeyboardAndMouse::*,
+    UI::Shell::*, UI::WindowsAndMessaging::*,
 };
 
 #[inline]
@@ -13,6 +13,22 @@ pub fn bstr(src: &[u8]) -> Vec<u8> {
     src.iter().cloned().chain(Some(0)).collect()
 }
 
+/// Decodes a string in Shift-JIS such as the text of the controls of MMD.
+pub fn from_sjis(src: &[u8]) -> String {
+    const CP_SJIS: u32 = 932;
+    if src.is_empty() {
+        return String::new();
+    }
+    unsafe {
+        let flags = MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0);
+        let src_ptr = PSTR(src.as_ptr() as _);
+        let len = MultiByteToWideChar(CP_SJIS, flags, src_ptr, src.len() as _, PWSTR::default(), 0);
+        let mut buffer = vec![0; len.max(0) as usize];
+        let len = MultiByteToWideChar(CP_SJIS, flags, src_ptr, src.len() as _, PWSTR(buffer.as_mut_ptr()), len);
+        String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+    }
+}
+
 pub fn get_system_directory() -> std::path::PathBuf {
     unsafe {
         let mut buffer = Vec::new();